@@ -0,0 +1,26 @@
+
+//! Everything that differs between lamp boards. Pick one with the `desk` (default)
+//! or `module` feature, e.g. `cargo build --no-default-features --features module`.
+
+use core::ops::Range;
+
+use embassy_rp::flash::ERASE_SIZE;
+
+#[cfg(all(feature = "desk", feature = "module"))]
+compile_error!("select exactly one board feature: `desk` or `module`");
+
+#[cfg(not(any(feature = "desk", feature = "module")))]
+compile_error!("select a board feature: `desk` or `module`");
+
+#[cfg(feature = "desk")]
+mod desk;
+#[cfg(feature = "desk")]
+pub use desk::*;
+
+#[cfg(feature = "module")]
+mod module;
+#[cfg(feature = "module")]
+pub use module::*;
+
+// the store lives in the last STORAGE_PAGES erase pages of flash
+pub const STORAGE_RANGE: Range<u32> = ((FLASH_SIZE - STORAGE_PAGES * ERASE_SIZE) as u32)..(FLASH_SIZE as u32);
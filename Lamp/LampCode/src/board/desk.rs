@@ -0,0 +1,46 @@
+
+use assign_resources::assign_resources;
+use embassy_rp::peripherals::{self, PIO0};
+use lamp_core::power::{PowerBudget, DESK_BUDGET};
+
+use crate::output::PioLeds;
+
+pub const BOARD_NAME: &str = "desk";
+
+pub const LED_COUNT: usize = 5;
+pub type Leds = PioLeds<PIO0, 1, LED_COUNT>;
+
+// runs off usb-c
+pub const POWER_BUDGET: PowerBudget = DESK_BUDGET;
+
+pub const FLASH_SIZE: usize = 2 * 1024 * 1024;
+pub const STORAGE_PAGES: usize = 4;
+
+pub const FLIP_ENCODER: bool = true;
+
+assign_resources! {
+  boot: BootResources {
+    led_pin: PIN_19
+  }
+  encoder: EncoderResources {
+    a_pin: PIN_24,
+    b_pin: PIN_25,
+    led_pin: PIN_4
+  }
+  button: ButtonResources {
+    pin: PIN_26,
+    led_pin: PIN_27
+  }
+  manager: ManagerResources {
+    led_pin: PIN_2
+  }
+  led: LedResources {
+    data_pin: PIN_12,
+    dma_chan: DMA_CH1,
+    en_pin: PIN_11,
+    en_led_pin: PIN_1
+  },
+  flash: FlashResources {
+    dma_chan: DMA_CH0
+  }
+}
@@ -0,0 +1,46 @@
+
+use assign_resources::assign_resources;
+use embassy_rp::peripherals::{self, PIO0};
+use lamp_core::power::{PowerBudget, MODULE_BUDGET};
+
+use crate::output::PioLeds;
+
+pub const BOARD_NAME: &str = "module";
+
+pub const LED_COUNT: usize = 5;
+pub type Leds = PioLeds<PIO0, 1, LED_COUNT>;
+
+// this board's share of its section of the installation
+pub const POWER_BUDGET: PowerBudget = MODULE_BUDGET;
+
+pub const FLASH_SIZE: usize = 2 * 1024 * 1024;
+pub const STORAGE_PAGES: usize = 4;
+
+pub const FLIP_ENCODER: bool = false;
+
+assign_resources! {
+  boot: BootResources {
+    led_pin: PIN_24
+  }
+  encoder: EncoderResources {
+    a_pin: PIN_10,
+    b_pin: PIN_11,
+    led_pin: PIN_4
+  }
+  button: ButtonResources {
+    pin: PIN_6,
+    led_pin: PIN_5
+  }
+  manager: ManagerResources {
+    led_pin: PIN_3
+  }
+  led: LedResources {
+    data_pin: PIN_12,
+    dma_chan: DMA_CH1,
+    en_pin: PIN_17,
+    en_led_pin: PIN_2
+  },
+  flash: FlashResources {
+    dma_chan: DMA_CH0
+  }
+}
@@ -1,11 +1,21 @@
 
+use core::{cell::RefCell, future::pending};
+
+use embassy_futures::select::{select, Either};
 use embassy_rp::gpio::{Input, Level, Output};
-use embassy_time::{with_deadline, Duration, Instant, Timer};
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+use embassy_time::{Duration, Instant, Timer};
 
-use crate::common::{EVENT_CHANNEL, Events};
+use lamp_core::{
+  gesture::{Gesture, GestureRecognizer},
+  overlay::RESET_COUNTDOWN
+};
 
+use crate::{
+  warn,
+  common::{EVENT_CHANNEL, Events}
+};
 
-const RESET_TIMEOUT_IN_MILISECONDS: u64 = 2000;
 
 pub struct Debouncer<'a> {
   input: Input<'a>,
@@ -32,6 +42,23 @@ impl<'a> Debouncer<'a> {
     }
   }
 
+  // like debounce, but against a level we already know; the edge is never lost if
+  // the wait gets cancelled, since nothing is read before it starts waiting
+  pub async fn debounce_from(&mut self, level: Level) -> Level {
+    loop {
+      if self.input.get_level() == level {
+        self.input.wait_for_any_edge().await;
+      }
+
+      Timer::after(self.debounce).await;
+
+      let new_level = self.input.get_level();
+      if new_level != level {
+        break new_level;
+      }
+    }
+  }
+
   pub fn get_level(&mut self) -> Level {
     self.input.get_level()
   }
@@ -41,33 +68,73 @@ impl<'a> Debouncer<'a> {
   }
 }
 
+// the encoder task reports turns through here, so a hold while turning isn't also a click
+static GESTURES: Mutex<CriticalSectionRawMutex, RefCell<GestureRecognizer>> =
+  Mutex::new(RefCell::new(GestureRecognizer::new()));
+
+// Some(HoldTurn) if the button is down, None for a plain turn
+pub(crate) fn hold_turn(is_increment: bool) -> Option<Gesture> {
+  GESTURES.lock(|gestures| gestures.borrow_mut().turn(is_increment))
+}
+
+// the manager gets to a LongPress whenever it drains EVENT_CHANNEL, possibly after the
+// release already cancelled the countdown; only start it while that hold is still going.
+// the check and the cancel on release share the lock, so the two can't interleave
+pub(crate) fn start_countdown() {
+  GESTURES.lock(|gestures| {
+    if gestures.borrow().is_long_press() {
+      RESET_COUNTDOWN.start();
+    }
+  });
+}
+
+async fn wait_for_deadline(deadline: Option<Instant>) {
+  match deadline {
+    Some(deadline) => Timer::at(deadline).await,
+    None => pending().await
+  }
+}
+
 #[embassy_executor::task]
 pub async fn button_task(mut btn: Debouncer<'static>, mut led: Output<'static>) {
   // note; button must be a pullup
 
   let sender = EVENT_CHANNEL.sender();
 
-  loop {
-    // wait for the button to be let go
-    btn.wait_high().await;
+  // wait for the button to be let go
+  btn.wait_high().await;
+  led.set_low();
+  let mut level = Level::High;
 
-    // wait for a button press
-    led.set_low();
-    btn.debounce().await;
-
-    let start = Instant::now();
-    led.set_high();
-
-    // check if its a long press
-    match with_deadline(start + Duration::from_millis(RESET_TIMEOUT_IN_MILISECONDS), btn.debounce()).await {
-      // Button released <3s
-      Ok(_) => {
-        sender.send(Events::ButtonPress(false)).await;
+  loop {
+    let deadline = GESTURES.lock(|gestures| gestures.borrow().deadline());
+    let gesture = match select(btn.debounce_from(level), wait_for_deadline(deadline)).await {
+      // button pressed
+      Either::First(Level::Low) => {
+        level = Level::Low;
+        led.set_high();
+        GESTURES.lock(|gestures| gestures.borrow_mut().press(Instant::now()))
       }
-      // button held for >3s
-      Err(_) => {
-        sender.send(Events::ButtonPress(true)).await;
+      // button released
+      Either::First(Level::High) => {
+        level = Level::High;
+        led.set_low();
+        GESTURES.lock(|gestures| {
+          RESET_COUNTDOWN.cancel();
+          gestures.borrow_mut().release(Instant::now())
+        })
+      }
+      // a click window closed, or the button has been held long enough
+      Either::Second(_) => {
+        GESTURES.lock(|gestures| gestures.borrow_mut().poll(Instant::now()))
+      }
+    };
+    // never wait on the manager; a press that lands while it is stuck writing flash
+    // with a full channel is dropped rather than stalling the next edge
+    if let Some(gesture) = gesture {
+      if sender.try_send(Events::Button(gesture)).is_err() {
+        warn!("Event channel full, dropped {:?}", gesture);
       }
     }
   }
-}
\ No newline at end of file
+}
@@ -1,13 +1,15 @@
 
-use defmt::Format;
-use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel};
-
-#[derive(Format, PartialEq, Eq)]
-pub enum Events {
-  ButtonPress(bool),
-  EncoderTurn(bool),
-  ModeTimeout,
-  SaveStore
-}
-
-pub static EVENT_CHANNEL: channel::Channel<CriticalSectionRawMutex, Events, 10> = channel::Channel::new();
\ No newline at end of file
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel, signal};
+
+use lamp_core::encoder::TurnAccumulator;
+
+pub use lamp_core::Events;
+
+pub static EVENT_CHANNEL: channel::Channel<CriticalSectionRawMutex, Events, 10> = channel::Channel::new();
+
+// encoder motion skips the channel: it piles up here and the manager drains it when
+// it gets to it, so the encoder never waits on a full channel
+pub static TURNS: TurnAccumulator = TurnAccumulator::new();
+// single steps turned while the button is held
+pub static HOLD_TURNS: TurnAccumulator = TurnAccumulator::new();
+pub static TURN_SIGNAL: signal::Signal<CriticalSectionRawMutex, ()> = signal::Signal::new();
\ No newline at end of file
@@ -7,26 +7,35 @@ use embassy_rp::{
   }
 };
 
-use crate::common::{EVENT_CHANNEL, Events};
+use embassy_time::Instant;
+
+use lamp_core::{encoder::Acceleration, gesture::Gesture};
+
+use crate::{
+  button::hold_turn,
+  common::{HOLD_TURNS, TURNS, TURN_SIGNAL}
+};
 
 
 #[embassy_executor::task]
 pub async fn encoder_task(mut encoder: PioEncoder<'static, PIO0, 0>, mut led: Output<'static>, flip_direction: bool) {
-  let sender = EVENT_CHANNEL.sender();
   let mut count = 0;
   let cc_wise = if flip_direction { -1 } else { 1 };
   let c_wise = cc_wise * -1;
+  let mut acceleration = Acceleration::new();
   loop {
-    count += match encoder.read().await {
-      Direction::CounterClockwise => {
-        sender.send(Events::EncoderTurn(flip_direction)).await;
-        cc_wise
-      },
-      Direction::Clockwise => {
-        sender.send(Events::EncoderTurn(!flip_direction)).await;
-        c_wise
-      },
+    let (is_increment, step) = match encoder.read().await {
+      Direction::CounterClockwise => (flip_direction, cc_wise),
+      Direction::Clockwise => (!flip_direction, c_wise),
     };
+    // turning with the button held is a gesture of its own, and never accelerated
+    match hold_turn(is_increment) {
+      Some(Gesture::HoldTurn(step)) => HOLD_TURNS.add(step),
+      _ => TURNS.add(acceleration.step(Instant::now(), is_increment))
+    }
+    // never blocks; the manager picks up everything that piled up since it last looked
+    TURN_SIGNAL.signal(());
+    count += step;
     if count % 2 == 0 {
       led.set_low();
     } else {
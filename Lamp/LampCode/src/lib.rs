@@ -1,5 +1,9 @@
 #![no_std]
 
+mod logging;
+
+pub mod board;
+
 mod common;
 
 mod button;
@@ -8,15 +12,17 @@ pub use button::{button_task, Debouncer};
 mod encoder;
 pub use encoder::encoder_task;
 
-mod color;
-
-mod walker;
-
 mod store;
-pub use store::load_store;
+pub use store::{load_history, load_presets, load_store};
+
+mod output;
+pub use output::{PioLeds, PwmLeds, PWM_TOP};
 
 mod lights;
 pub use lights::lights_task;
 
 mod manager;
-pub use manager::manager_task;
\ No newline at end of file
+pub use manager::manager_task;
+
+mod usb;
+pub use usb::start_usb;
\ No newline at end of file
@@ -0,0 +1,40 @@
+
+//! Log macros that always go to defmt and, with the `usb-log` feature, are also
+//! mirrored to the USB logger. The format string has to suit both, so stick to
+//! `{}` / `{:?}` on values that are both `defmt::Format` and `Debug`.
+
+#[macro_export]
+macro_rules! debug {
+  ($($arg:tt)*) => {{
+    ::defmt::debug!($($arg)*);
+    #[cfg(feature = "usb-log")]
+    ::log::debug!($($arg)*);
+  }};
+}
+
+#[macro_export]
+macro_rules! info {
+  ($($arg:tt)*) => {{
+    ::defmt::info!($($arg)*);
+    #[cfg(feature = "usb-log")]
+    ::log::info!($($arg)*);
+  }};
+}
+
+#[macro_export]
+macro_rules! warn {
+  ($($arg:tt)*) => {{
+    ::defmt::warn!($($arg)*);
+    #[cfg(feature = "usb-log")]
+    ::log::warn!($($arg)*);
+  }};
+}
+
+#[macro_export]
+macro_rules! error {
+  ($($arg:tt)*) => {{
+    ::defmt::error!($($arg)*);
+    #[cfg(feature = "usb-log")]
+    ::log::error!($($arg)*);
+  }};
+}
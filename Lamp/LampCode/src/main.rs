@@ -1,16 +1,18 @@
 #![no_std]
 #![no_main]
 
-use embassy_rp::flash::{Async, Flash, ERASE_SIZE};
-use lamp::{load_store, Debouncer, button_task, encoder_task, lights_task, manager_task};
+use embassy_rp::flash::{Async, Flash};
+use lamp::{load_history, load_presets, load_store, split_resources, start_usb, Debouncer, PioLeds, button_task, encoder_task, lights_task, manager_task};
+// split_resources! expands to the board's resource structs, so they need to be in scope
+use lamp::board::{self, *};
 
-use defmt::*;
+use lamp::info;
 
 use embassy_executor::Spawner;
 use embassy_time::{Duration, Timer};
 use embassy_rp::bind_interrupts;
 use embassy_rp::gpio::{Input, Level, Output, Pull};
-use embassy_rp::peripherals::{self, PIO0};
+use embassy_rp::peripherals::PIO0;
 use embassy_rp::pio::{InterruptHandler, Pio};
 use embassy_rp::pio_programs::ws2812::{PioWs2812, PioWs2812Program};
 use embassy_rp::pio_programs::rotary_encoder::{PioEncoder, PioEncoderProgram};
@@ -19,43 +21,10 @@ use embassy_rp::watchdog::Watchdog;
 use {defmt_rtt as _, panic_probe as _};
 
 
-use assign_resources::assign_resources;
-
-assign_resources! {
-  boot: BootResources {
-    led_pin: PIN_19
-  }
-  encoder: EncoderResources {
-    a_pin: PIN_24,
-    b_pin: PIN_25,
-    led_pin: PIN_4
-  }
-  button: ButtonResources {
-    pin: PIN_26,
-    led_pin: PIN_27
-  }
-  manager: ManagerResources {
-    led_pin: PIN_2
-  }
-  led: LedResources {
-    data_pin: PIN_12,
-    dma_chan: DMA_CH1,
-    en_pin: PIN_11,
-    en_led_pin: PIN_1
-  },
-  flash: FlashResources {
-    dma_chan: DMA_CH0
-  }
-}
-
 bind_interrupts!(struct Irqs {
   PIO0_IRQ_0 => InterruptHandler<PIO0>;
 });
 
-const ADDR_OFFSET: u32 = 0x100000;
-const FLASH_SIZE: u32 = 2 * 1024 * 1024;
-const _FLASH_SIZE: usize = FLASH_SIZE as usize;
-
 #[embassy_executor::main]
 async fn main(spawner: Spawner) {
 
@@ -79,11 +48,11 @@ async fn main(spawner: Spawner) {
 
   info!("Initialize State");
 
-  let mut flash = Flash::<_, Async, _FLASH_SIZE>::new(p.FLASH, r.flash.dma_chan);
-  let flash_range_start = (flash.capacity() - 4 as usize * ERASE_SIZE) as u32;
-  let flash_range_end = flash.capacity() as u32;
-  let map_flash_range = flash_range_start..flash_range_end;
+  let mut flash = Flash::<_, Async, { board::FLASH_SIZE }>::new(p.FLASH, r.flash.dma_chan);
+  let map_flash_range = board::STORAGE_RANGE;
   load_store(&mut flash, map_flash_range.clone()).await;
+  load_presets(&mut flash, map_flash_range.clone()).await;
+  let history = load_history(&mut flash, map_flash_range.clone()).await;
 
   info!("Initialize, start up button");
 
@@ -102,7 +71,7 @@ async fn main(spawner: Spawner) {
   let enc_prg = PioEncoderProgram::new(&mut common);
   let enc = PioEncoder::new(&mut common, sm0, r.encoder.a_pin, r.encoder.b_pin, &enc_prg);
   let enc_led = Output::new(r.encoder.led_pin, Level::Low);
-  spawner.must_spawn(encoder_task(enc, enc_led, true));
+  spawner.must_spawn(encoder_task(enc, enc_led, board::FLIP_ENCODER));
 
   info!("Initialize, start up leds");
 
@@ -110,12 +79,16 @@ async fn main(spawner: Spawner) {
   let lts = PioWs2812::new(&mut common, sm1, r.led.dma_chan, r.led.data_pin, &lts_prg);
   let en = Output::new(r.led.en_pin, Level::Low);
   let en_led = Output::new(r.led.en_led_pin, Level::Low);
-  spawner.must_spawn(lights_task(lts, en, en_led));
+  spawner.must_spawn(lights_task(PioLeds::new(lts, en, en_led)));
 
   info!("Initialize, start manager");
 
   let mng_led = Output::new(r.manager.led_pin, Level::Low);
-  spawner.must_spawn(manager_task(spawner, mng_led, flash, map_flash_range));
+  spawner.must_spawn(manager_task(spawner, mng_led, flash, map_flash_range, history));
+
+  info!("Initialize, start usb console");
+
+  start_usb(spawner, p.USB);
 
   info!("Main task finished; feeding watchdog");
 
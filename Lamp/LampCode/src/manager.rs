@@ -5,169 +5,174 @@ use core::ops::Range;
 use embassy_executor::Spawner;
 use embassy_futures::select::{select, Either};
 use embassy_rp::{flash::{Async, Flash}, gpio::Output, peripherals::FLASH};
-use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal};
-use embassy_time::{Duration, Timer};
+use embassy_sync::signal::Signal;
+use embassy_time::Instant;
+use heapless::Vec;
 
-use crate::{
-  common::{Events, EVENT_CHANNEL},
-  store::{write_store, reset_state, update_brightness, update_color, update_value}
+use lamp_core::{
+  gesture::Gesture,
+  history::ResetHistory,
+  manager::{
+    reduce, run_mode_timeout, run_save_debounce, Command, ManagerState, ModeCommands, ModeSignal, SaveCommands,
+    SaveSignal
+  },
+  overlay::{MODE_INDICATION, RESET_COUNTDOWN},
+  presets::PRESET_COUNT,
+  store::{nudge_brightness, reset_state, toggle_power, update_brightness, update_color, update_value, STORE}
 };
 
-// 3.5 minutes, meh
-const MODE_TIMEOUT_IN_SECONDS: u64 = 60 * 3 + 30;
-const SAVE_TIMEOUT_IN_MILISECONDS: u64 = 1000;
-
-// can't get around having this defined twice... wish I knew rust better
-const FLASH_SIZE: usize = 2 * 1024 * 1024;
-
-enum ManagerStates {
-  Brightness,
-  Value,
-  Color
-}
-
-fn transition_manager_state(current_state: ManagerStates) -> ManagerStates {
-  match current_state {
-    ManagerStates::Brightness => {
-      ManagerStates::Color
-    }
-    ManagerStates::Color => {
-      ManagerStates::Value
-    }
-    ManagerStates::Value => {
-      ManagerStates::Brightness
-    }
-  }
-}
+use crate::{
+  info,
+  board::FLASH_SIZE,
+  button::start_countdown,
+  common::{Events, EVENT_CHANNEL, HOLD_TURNS, TURNS, TURN_SIGNAL},
+  store::{clear_presets, write_history, write_preset, write_store, PRESETS}
+};
 
-#[derive(PartialEq, Eq)]
-enum ModeCommands {
-  Start,
-  Reset,
-  Stop
-}
+static MODE_SIGNAL: ModeSignal = Signal::new();
 
-static MODE_SIGNAL: signal::Signal<CriticalSectionRawMutex, ModeCommands> = signal::Signal::new();
+static SAVE_SIGNAL: SaveSignal = Signal::new();
 
-#[derive(PartialEq, Eq)]
-enum SaveCommands {
-  Save,
+// debounced the same way as an encoder turn
+pub(crate) fn schedule_save() {
+  SAVE_SIGNAL.signal(SaveCommands::Save);
 }
 
-static SAVE_SIGNAL: signal::Signal<CriticalSectionRawMutex, SaveCommands> = signal::Signal::new();
-
 #[embassy_executor::task]
 pub async fn manager_task(
   spawner: Spawner,
   mut led: Output<'static>,
-  mut flash: Flash<'static, FLASH, Async, FLASH_SIZE>,
-  flash_range: Range<u32>
+  flash: Flash<'static, FLASH, Async, FLASH_SIZE>,
+  flash_range: Range<u32>,
+  history: ResetHistory
 ) {
   spawner.must_spawn(mode_timeout_task());
   spawner.must_spawn(save_task());
   let receiver = EVENT_CHANNEL.receiver();
-  let mut manager_state = ManagerStates::Brightness;
+  let saved_presets = PRESETS.lock(|presets| presets.borrow().saved());
+  let mut state = ManagerState::new(saved_presets, cfg!(feature = "long-hold-reset"));
+  let mut runner = CommandRunner { flash, flash_range, data_buffer: [0; 32], history };
   let mut count = 0;
-  let mut data_buffer = [0; 32];
   loop {
-    let event = receiver.receive().await;
-    match event {
-      Events::SaveStore => {
-        write_store(&mut flash, flash_range.clone(), &mut data_buffer).await;
-      }
-      Events::ModeTimeout => {
-        manager_state = ManagerStates::Brightness;
+    let mut events: Vec<Events, 2> = Vec::new();
+    match select(receiver.receive(), TURN_SIGNAL.wait()).await {
+      Either::First(event) => {
+        let _ = events.push(event);
       }
-      // long press
-      Events::ButtonPress(true) => {
-        manager_state = ManagerStates::Brightness;
-        MODE_SIGNAL.signal(ModeCommands::Stop);
-        SAVE_SIGNAL.signal(SaveCommands::Save);
-        reset_state();
+      Either::Second(_) => drain_turns(&mut events),
+    }
+    for event in events {
+      // a command can answer with an event of its own, like a preset write that went through
+      let mut pending = Some(event);
+      while let Some(event) = pending.take() {
+        let (next, commands) = reduce(state, event, Instant::now());
+        state = next;
+        for command in commands {
+          if let Some(followup) = runner.run(command).await {
+            pending = Some(followup);
+          }
+        }
       }
-      // short press
-      Events::ButtonPress(false) => {
-        manager_state = transition_manager_state(manager_state);
-        MODE_SIGNAL.signal(ModeCommands::Start);
+      // used for debug
+      count += 1;
+      if count % 2 == 0 {
+        led.set_low();
+      } else {
+        led.set_high();
       }
-      // encoder turn
-      Events::EncoderTurn(is_increment) => {
-        MODE_SIGNAL.signal(ModeCommands::Reset);
-        SAVE_SIGNAL.signal(SaveCommands::Save);
-        match manager_state {
-            ManagerStates::Brightness => update_brightness(is_increment),
-            ManagerStates::Value => update_value(is_increment),
-            ManagerStates::Color => update_color(is_increment),
-        }
+    }
+  }
+}
+
+// everything the reducer's commands touch
+struct CommandRunner {
+  flash: Flash<'static, FLASH, Async, FLASH_SIZE>,
+  flash_range: Range<u32>,
+  data_buffer: [u8; 32],
+  history: ResetHistory,
+}
+
+impl CommandRunner {
+  async fn run(&mut self, command: Command) -> Option<Events> {
+    match command {
+      Command::StartModeTimeout => MODE_SIGNAL.signal(ModeCommands::Start),
+      Command::ResetModeTimeout => MODE_SIGNAL.signal(ModeCommands::Reset),
+      Command::StopModeTimeout => MODE_SIGNAL.signal(ModeCommands::Stop),
+      Command::ScheduleSave => SAVE_SIGNAL.signal(SaveCommands::Save),
+      Command::WriteStore => write_store(&mut self.flash, self.flash_range.clone(), &mut self.data_buffer).await,
+      Command::ShowMode(mode) => MODE_INDICATION.show(mode),
+      Command::StartCountdown => start_countdown(),
+      Command::CancelCountdown => RESET_COUNTDOWN.cancel(),
+      Command::UpdateBrightness(delta) => update_brightness(delta),
+      Command::NudgeBrightness(delta) => nudge_brightness(delta),
+      Command::UpdateColor(delta) => update_color(delta),
+      Command::UpdateValue(delta) => update_value(delta),
+      Command::TogglePower => toggle_power(),
+      Command::ResetStore => self.reset_with_undo().await,
+      Command::UndoReset => self.undo_reset().await,
+      Command::ClearPresets => clear_presets(&mut self.flash, self.flash_range.clone(), &mut self.data_buffer).await,
+      Command::SavePreset(slot, name) => return self.save_preset(slot, &name).await,
+      Command::RecallPreset(slot) => {
+        PRESETS.lock(|presets| presets.borrow().recall(slot, &STORE));
       }
     }
-    // used for debug
-    count += 1;
-    if count % 2 == 0 {
-      led.set_low();
-    } else {
-      led.set_high();
+    None
+  }
+
+  // remembers the settings before resetting them, so the reset can be undone
+  async fn reset_with_undo(&mut self) {
+    self.history.push(STORE.settings());
+    reset_state();
+    write_history(&mut self.flash, self.flash_range.clone(), &mut self.data_buffer, &self.history).await;
+  }
+
+  async fn undo_reset(&mut self) {
+    let Some(settings) = self.history.pop() else {
+      info!("Nothing to undo");
+      return;
+    };
+    STORE.apply_settings(&settings);
+    SAVE_SIGNAL.signal(SaveCommands::Save);
+    write_history(&mut self.flash, self.flash_range.clone(), &mut self.data_buffer, &self.history).await;
+  }
+
+  // the RAM copy and the manager only learn about the preset once it is on flash
+  async fn save_preset(&mut self, slot: u8, name: &str) -> Option<Events> {
+    let Some(preset) = PRESETS.lock(|presets| presets.borrow().build(slot, name, STORE.settings())) else {
+      info!("No preset slot {}; there are {}", slot, PRESET_COUNT);
+      return None;
+    };
+    if !write_preset(&mut self.flash, self.flash_range.clone(), &mut self.data_buffer, slot, &preset).await {
+      return None;
     }
+    PRESETS.lock(|presets| presets.borrow_mut().insert(slot, preset));
+    Some(Events::PresetSaved(slot))
+  }
+}
+
+// turns the piled up encoder motion back into events
+fn drain_turns(events: &mut Vec<Events, 2>) {
+  let delta = HOLD_TURNS.take();
+  if delta != 0 {
+    let _ = events.push(Events::Button(Gesture::HoldTurn(delta)));
+  }
+  let delta = TURNS.take();
+  if delta != 0 {
+    let _ = events.push(Events::EncoderTurn(delta));
+  }
+  // more than fits in one step; come back for the rest
+  if !HOLD_TURNS.is_empty() || !TURNS.is_empty() {
+    TURN_SIGNAL.signal(());
   }
 }
 
+// the bodies live in lamp_core, where they're tested on embassy's executor against a mock clock
 #[embassy_executor::task]
 async fn mode_timeout_task() {
-  let sender = EVENT_CHANNEL.sender();
-  loop {
-    // wait for an initial signal
-    let sig = MODE_SIGNAL.wait().await;
-    // we don't care about resets / stops; only start the timer if we had a button push
-    if sig != ModeCommands::Start {
-      continue;
-    }
-    // afterwards, loop until we timeout; then we reset and wait for another one
-    loop {
-      let futures = select(
-        Timer::after(Duration::from_secs(MODE_TIMEOUT_IN_SECONDS)),
-        MODE_SIGNAL.wait()
-      ).await;
-      match futures {
-        // let the manager know we timed out; go back to wating
-        Either::First(_) => {
-          sender.send(Events::ModeTimeout).await;
-          break;
-        }
-        // manager already reset state; go back to waiting
-        Either::Second(ModeCommands::Stop) => {
-          break;
-        },
-        // user has taken some action; keep waiting for timeout
-        _ => {
-          continue;
-        }
-      }
-    }
-  }
+  run_mode_timeout(&MODE_SIGNAL, &EVENT_CHANNEL).await
 }
 
 #[embassy_executor::task]
 async fn save_task() {
-  let sender = EVENT_CHANNEL.sender();
-  loop {
-    // wait for an initial signal
-    SAVE_SIGNAL.wait().await;
-    loop {
-      let futures = select(
-        Timer::after(Duration::from_millis(SAVE_TIMEOUT_IN_MILISECONDS)),
-        SAVE_SIGNAL.wait()
-      ).await;
-      match futures {
-        // save the state, go back to waiting
-        Either::First(_) => {
-          sender.send(Events::SaveStore).await;
-          break;
-        }
-        // user has taken some action; keep waiting for timeout
-        _ => {
-          continue;
-        }
-      }
-    }
-  }
-}
\ No newline at end of file
+  run_save_debounce(&SAVE_SIGNAL, &EVENT_CHANNEL).await
+}
@@ -0,0 +1,87 @@
+
+use core::future::{ready, Future};
+
+use embassy_rp::{
+  gpio::{Level, Output},
+  pio::Instance,
+  pio_programs::ws2812::PioWs2812,
+  pwm::{Config, Pwm}
+};
+
+use lamp_core::{
+  color::RGBA8,
+  output::{analog_duty, LedOutput}
+};
+
+// an addressable ws2812 / sk6812 strip on a pio state machine
+pub struct PioLeds<P: Instance + 'static, const S: usize, const N: usize> {
+  strip: PioWs2812<'static, P, S, N>,
+  en: Output<'static>,
+  // shows whether the supply is on
+  en_led: Output<'static>,
+}
+
+impl<P: Instance + 'static, const S: usize, const N: usize> PioLeds<P, S, N> {
+  pub fn new(strip: PioWs2812<'static, P, S, N>, en: Output<'static>, en_led: Output<'static>) -> Self {
+    Self { strip, en, en_led }
+  }
+}
+
+impl<P: Instance + 'static, const S: usize, const N: usize> LedOutput<N> for PioLeds<P, S, N> {
+  fn set_enabled(&mut self, enabled: bool) {
+    let level = if enabled { Level::High } else { Level::Low };
+    self.en.set_level(level);
+    self.en_led.set_level(level);
+  }
+
+  fn write(&mut self, frame: &[RGBA8; N]) -> impl Future<Output = ()> {
+    self.strip.write_rgba(frame)
+  }
+}
+
+// 125 MHz / 4096 is ~30 kHz, well clear of visible flicker
+pub const PWM_TOP: u16 = 4095;
+
+// a single analog rgbw led, one pwm channel per color: red / green on one slice,
+// blue / white on another
+pub struct PwmLeds {
+  red_green: Pwm<'static>,
+  blue_white: Pwm<'static>,
+  en: Output<'static>,
+  config: Config,
+}
+
+impl PwmLeds {
+  pub fn new(red_green: Pwm<'static>, blue_white: Pwm<'static>, en: Output<'static>) -> Self {
+    let mut config = Config::default();
+    config.top = PWM_TOP;
+    let mut leds = Self { red_green, blue_white, en, config };
+    leds.set_duty([0; 4]);
+    leds
+  }
+
+  fn set_duty(&mut self, [red, green, blue, white]: [u16; 4]) {
+    self.config.compare_a = red;
+    self.config.compare_b = green;
+    self.red_green.set_config(&self.config);
+    self.config.compare_a = blue;
+    self.config.compare_b = white;
+    self.blue_white.set_config(&self.config);
+  }
+}
+
+impl<const N: usize> LedOutput<N> for PwmLeds {
+  fn set_enabled(&mut self, enabled: bool) {
+    // nothing left lit behind a supply that's coming back on later
+    if !enabled {
+      self.set_duty([0; 4]);
+    }
+    self.en.set_level(if enabled { Level::High } else { Level::Low });
+  }
+
+  // the compare registers take effect on the next pwm period; nothing to wait for
+  fn write(&mut self, frame: &[RGBA8; N]) -> impl Future<Output = ()> {
+    self.set_duty(analog_duty(frame, PWM_TOP));
+    ready(())
+  }
+}
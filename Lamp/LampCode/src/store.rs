@@ -1,202 +1,242 @@
 
-use core::ops::Range;
-
-use defmt::*;
+use core::{cell::RefCell, ops::Range};
 
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
 use embedded_storage_async::nor_flash::MultiwriteNorFlash;
-use heapless::Vec;
-use portable_atomic::{AtomicU8, Ordering};
 use sequential_storage::{
   cache::NoCache,
-  map::{store_item, fetch_item},
+  map::{store_item, fetch_item, remove_item},
   erase_all
 };
 
-use crate::{
-  color::{eased_step, LampColor, COLOR_MAX, RGBA8},
-  walker::{WalkerSetting, WALKER_FADE_IN_STEP, WALKER_INTENSITY_MAX}
-}; 
-
-const BRIGHTNESS_INCREMENT: u8 = 16;
-const BRIGHTNESS_EASE_STEP: f32 = 16.0f32;
-
-#[derive(Default, Debug)]
-struct AtomicStore {
-  brightness: AtomicU8,
-  color: AtomicU8,
-  value: AtomicU8,
-}
-
-impl AtomicStore {
-  fn to_vec(&self) -> Vec<u8, 4> {
-    let brightness = STORE.brightness.load(Ordering::Relaxed);
-    let color = STORE.color.load(Ordering::Relaxed);
-    let value = STORE.value.load(Ordering::Relaxed);
-    let mut out = Vec::new();
-    out.push(brightness).unwrap();
-    out.push(color).unwrap();
-    out.push(value).unwrap();
-    out
-  }
+use crate::{error, info, warn};
 
-  fn from_bytes(&self, data: &[u8]) {
-    let brightness = data[0];
-    let color = data[1].max(0).min(COLOR_MAX);
-    let value = data[2].max(0).min(WALKER_INTENSITY_MAX);
-    self.brightness.store(brightness, Ordering::Relaxed);
-    self.color.store(color, Ordering::Relaxed);
-    self.value.store(value, Ordering::Relaxed);
-  }
-}
-
-
-static STORE: AtomicStore = AtomicStore {
-  brightness: AtomicU8::new(255),
-  color: AtomicU8::new(0),
-  value: AtomicU8::new(0),
+use lamp_core::{
+  history::{self, ResetHistory, HISTORY_BUFFER_SIZE, HISTORY_KEY},
+  persist::{decode, encode, migrate_legacy, LEGACY_STORE_KEY, SETTINGS_BUFFER_SIZE, STORE_KEY},
+  presets::{self, preset_key, Preset, PresetSlots, PRESET_BUFFER_SIZE, PRESET_COUNT},
+  store::{reset_state, STORE}
 };
 
-pub async fn load_store<E: defmt::Format>(
+// shared between the manager, which saves and recalls, and the console, which lists
+pub static PRESETS: Mutex<CriticalSectionRawMutex, RefCell<PresetSlots>> =
+  Mutex::new(RefCell::new(PresetSlots::new()));
+
+pub async fn load_store<E: defmt::Format + core::fmt::Debug>(
   flash: &mut impl MultiwriteNorFlash<Error = E>,
   flash_range: Range<u32>,
 ) {
 
-  let mut data_buffer = [0; 32];
+  let mut data_buffer = [0; SETTINGS_BUFFER_SIZE];
   let fetched = fetch_item::<u8, &[u8], _>(
     flash,
     flash_range.clone(),
     &mut NoCache::new(),
     &mut data_buffer,
-    &0u8,
+    &STORE_KEY,
   ).await;
-  if let Ok(Some(raw_store)) = fetched {
-    if raw_store.len() == 3 {
-      STORE.from_bytes(raw_store);
+  match fetched {
+    Ok(Some(raw_store)) => match decode(raw_store) {
+      Ok(settings) => {
+        STORE.apply_settings(&settings);
+        return;
+      }
+      // leave flash alone; the next save overwrites it with something we can read
+      Err(e) => warn!("Persisted store is either the wrong format or corrupted: {:?}", e),
+    },
+    Ok(None) => {
+      if load_legacy_store(flash, flash_range.clone()).await {
+        return;
+      }
+      warn!("No data in the persisted store");
+    }
+    Err(e) => {
+      error!("Persisted store is corrupted: {:?}", e);
+      reset_state();
+      let _ = erase_all(flash, flash_range.clone()).await;
       return;
-    } else {
-      warn!("Persisted store is either the wrong format or corrupted");
     }
-  } else if let Err(e) = fetched {
-    error!("Persisted store is corrupted: {:?}", e);
-  } else {
-    warn!("No data in the persisted store");
   }
   reset_state();
-  let _ = erase_all(flash, flash_range.clone()).await;
 }
 
-pub async fn write_store<E: defmt::Format>(
+// migrates a store written before it was versioned; returns true if there was one
+async fn load_legacy_store<E: defmt::Format + core::fmt::Debug>(
+  flash: &mut impl MultiwriteNorFlash<Error = E>,
+  flash_range: Range<u32>,
+) -> bool {
+  let mut data_buffer = [0; SETTINGS_BUFFER_SIZE];
+  let fetched = fetch_item::<u8, &[u8], _>(
+    flash,
+    flash_range.clone(),
+    &mut NoCache::new(),
+    &mut data_buffer,
+    &LEGACY_STORE_KEY,
+  ).await;
+  let settings = match fetched.map(|raw_store| raw_store.map(migrate_legacy)) {
+    Ok(Some(Ok(settings))) => settings,
+    Ok(Some(Err(e))) => {
+      warn!("Legacy persisted store could not be migrated: {:?}", e);
+      return false;
+    }
+    _ => return false
+  };
+  info!("Migrating legacy persisted store: {:?}", settings);
+  STORE.apply_settings(&settings);
+  write_store(flash, flash_range.clone(), &mut data_buffer).await;
+  let removed = remove_item(
+    flash,
+    flash_range.clone(),
+    &mut NoCache::new(),
+    &mut data_buffer,
+    &LEGACY_STORE_KEY,
+  ).await;
+  if let Err(e) = removed {
+    warn!("Failed to remove legacy persisted store with err: {:?}", e);
+  }
+  true
+}
+
+pub async fn write_store<E: defmt::Format + core::fmt::Debug>(
   flash: &mut impl MultiwriteNorFlash<Error = E>,
   flash_range: Range<u32>,
   data_buffer: &mut [u8]
 ) {
-  let to_store = STORE.to_vec();
+  let mut encode_buffer = [0; SETTINGS_BUFFER_SIZE];
+  let Ok(to_store) = encode(&STORE.settings(), &mut encode_buffer) else {
+    error!("Failed to encode store");
+    return;
+  };
   let stored = store_item(
     flash,
     flash_range.clone(),
     &mut NoCache::new(),
     data_buffer,
-    &0u8,
-    &to_store.as_slice(),
+    &STORE_KEY,
+    &to_store,
   ).await;
   if let Err(e) = stored {
     error!("Failed to persist store to disk with err: {:?}", e);
   }
 }
 
-pub fn reset_state() {
-  STORE.brightness.store(255, Ordering::Relaxed);
-  STORE.color.store(0, Ordering::Relaxed);
-  STORE.value.store(0, Ordering::Relaxed);
-}
-
-
-pub fn update_brightness(is_increment: bool) {
-  let mut brightness = STORE.brightness.load(Ordering::Relaxed);
-  brightness = if is_increment { 
-    brightness.saturating_add(BRIGHTNESS_INCREMENT) 
-  } else {  
-    brightness.saturating_sub(BRIGHTNESS_INCREMENT)
-  };
-  STORE.brightness.store(brightness, Ordering::Relaxed);
-}
-
-pub fn update_color(is_increment: bool) {
-  let old_color = STORE.color.load(Ordering::Relaxed);
-  let new_color = if is_increment {
-    if old_color >= COLOR_MAX { 0 } else { old_color + 1 }
-  } else {
-    if old_color == 0 { COLOR_MAX } else { old_color - 1 }
-  };
-  // info!("Old Color: {:?}; New Color: {:?}", old_color, new_color);
-  STORE.color.store(new_color, Ordering::Relaxed);
+pub async fn load_presets<E: defmt::Format + core::fmt::Debug>(
+  flash: &mut impl MultiwriteNorFlash<Error = E>,
+  flash_range: Range<u32>,
+) {
+  let mut data_buffer = [0; PRESET_BUFFER_SIZE];
+  for slot in 0..PRESET_COUNT {
+    let fetched = fetch_item::<u8, &[u8], _>(
+      flash,
+      flash_range.clone(),
+      &mut NoCache::new(),
+      &mut data_buffer,
+      &preset_key(slot),
+    ).await;
+    match fetched.map(|raw_preset| raw_preset.map(presets::decode)) {
+      Ok(Some(Ok(preset))) => PRESETS.lock(|presets| presets.borrow_mut().insert(slot, preset)),
+      Ok(Some(Err(e))) => warn!("Preset {} is either the wrong format or corrupted: {:?}", slot, e),
+      Ok(None) => {}
+      Err(e) => error!("Failed to read preset {} with err: {:?}", slot, e),
+    }
+  }
 }
 
-pub fn update_value(is_increment: bool) {
-  let old_value = STORE.value.load(Ordering::Relaxed);
-  let new_value = if is_increment {
-    if old_value >= WALKER_INTENSITY_MAX { 0 } else { old_value + 1 }
-  } else {
-    if old_value == 0 { WALKER_INTENSITY_MAX } else { old_value - 1 }
+// returns true once the preset is on flash
+pub async fn write_preset<E: defmt::Format + core::fmt::Debug>(
+  flash: &mut impl MultiwriteNorFlash<Error = E>,
+  flash_range: Range<u32>,
+  data_buffer: &mut [u8],
+  slot: u8,
+  preset: &Preset
+) -> bool {
+  let mut encode_buffer = [0; PRESET_BUFFER_SIZE];
+  let Ok(to_store) = presets::encode(preset, &mut encode_buffer) else {
+    error!("Failed to encode preset {}", slot);
+    return false;
   };
-  // info!("Old value: {:?}; new value: {:?}", old_value, new_value);
-  STORE.value.store(new_value, Ordering::Relaxed);
+  let stored = store_item(
+    flash,
+    flash_range.clone(),
+    &mut NoCache::new(),
+    data_buffer,
+    &preset_key(slot),
+    &to_store,
+  ).await;
+  if let Err(e) = stored {
+    error!("Failed to persist preset {} to disk with err: {:?}", slot, e);
+    return false;
+  }
+  true
 }
 
-#[derive(PartialEq)]
-pub struct Store {
-  pub brightness: u8,
-  pub color: RGBA8,
-  pub value: WalkerSetting
+pub async fn load_history<E: defmt::Format + core::fmt::Debug>(
+  flash: &mut impl MultiwriteNorFlash<Error = E>,
+  flash_range: Range<u32>,
+) -> ResetHistory {
+  let mut data_buffer = [0; HISTORY_BUFFER_SIZE];
+  let fetched = fetch_item::<u8, &[u8], _>(
+    flash,
+    flash_range.clone(),
+    &mut NoCache::new(),
+    &mut data_buffer,
+    &HISTORY_KEY,
+  ).await;
+  match fetched.map(|raw_history| raw_history.map(history::decode)) {
+    Ok(Some(Ok(history))) => history,
+    Ok(Some(Err(e))) => {
+      warn!("Reset history is either the wrong format or corrupted: {:?}", e);
+      ResetHistory::new()
+    }
+    Ok(None) => ResetHistory::new(),
+    Err(e) => {
+      error!("Failed to read reset history with err: {:?}", e);
+      ResetHistory::new()
+    }
+  }
 }
 
-pub fn get_store() -> Store {
-  let mut color = RGBA8 {
-    r: 0,
-    b: 0,
-    g: 0,
-    a: 0
-  };
-  color.from_u16(STORE.color.load(Ordering::Relaxed));
-  let value = WalkerSetting {
-    intensity: STORE.value.load(Ordering::Relaxed).into(),
-    pct: 255
+pub async fn write_history<E: defmt::Format + core::fmt::Debug>(
+  flash: &mut impl MultiwriteNorFlash<Error = E>,
+  flash_range: Range<u32>,
+  data_buffer: &mut [u8],
+  history: &ResetHistory
+) {
+  let mut encode_buffer = [0; HISTORY_BUFFER_SIZE];
+  let Ok(to_store) = history::encode(history, &mut encode_buffer) else {
+    error!("Failed to encode reset history");
+    return;
   };
-  return Store {
-    brightness: STORE.brightness.load(Ordering::Relaxed),
-    color: color,
-    value: value
+  let stored = store_item(
+    flash,
+    flash_range.clone(),
+    &mut NoCache::new(),
+    data_buffer,
+    &HISTORY_KEY,
+    &to_store,
+  ).await;
+  if let Err(e) = stored {
+    error!("Failed to persist reset history to disk with err: {:?}", e);
   }
 }
 
-pub fn update_store(store: &mut Store) {
-  store.brightness = STORE.brightness.load(Ordering::Relaxed);
-  store.color.from_u16(STORE.color.load(Ordering::Relaxed));
-  store.value.intensity = STORE.value.load(Ordering::Relaxed).into();
-}
-
-// returns true if the mode changed
-pub fn step_toward_store(target_store: &Store, local_store: &mut Store) -> bool {
-  if target_store.brightness != local_store.brightness {
-    let new_brightness = eased_step(
-      local_store.brightness, target_store.brightness, BRIGHTNESS_EASE_STEP
-    );
-    // info!(
-    //   "Old Brightness: {:?}; New Brightness: {:?}; Target Brightness: {:?}",
-    //   local_store.brightness, new_brightness, target_store.brightness
-    // );
-    local_store.brightness = new_brightness;
-  }
-  if target_store.color != local_store.color {
-    // target_store.color.print_color();
-    local_store.color.walk_toward(&target_store.color);
-  }
-  if target_store.value.intensity != local_store.value.intensity {
-    local_store.value.intensity = target_store.value.intensity;
-    local_store.value.pct = WALKER_FADE_IN_STEP;
-    return true;
-  } else if local_store.value.pct < 255 {
-    local_store.value.pct = local_store.value.pct.saturating_add(WALKER_FADE_IN_STEP);
+// forgets every preset, in RAM and on flash
+pub async fn clear_presets<E: defmt::Format + core::fmt::Debug>(
+  flash: &mut impl MultiwriteNorFlash<Error = E>,
+  flash_range: Range<u32>,
+  data_buffer: &mut [u8]
+) {
+  PRESETS.lock(|presets| presets.borrow_mut().clear());
+  for slot in 0..PRESET_COUNT {
+    let removed = remove_item(
+      flash,
+      flash_range.clone(),
+      &mut NoCache::new(),
+      data_buffer,
+      &preset_key(slot),
+    ).await;
+    if let Err(e) = removed {
+      warn!("Failed to remove preset {} with err: {:?}", slot, e);
+    }
   }
-  return false;
-}
\ No newline at end of file
+}
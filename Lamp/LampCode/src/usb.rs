@@ -0,0 +1,204 @@
+
+use embassy_executor::Spawner;
+use embassy_rp::{bind_interrupts, peripherals::USB, usb::{Driver, InterruptHandler}};
+use embassy_usb::{
+  class::cdc_acm::{CdcAcmClass, State},
+  driver::EndpointError,
+  Builder, Config, UsbDevice
+};
+use heapless::{String, Vec};
+use static_cell::StaticCell;
+
+use lamp_core::{
+  console::{handle_line, Followup, LampInfo, LogLevel, MAX_LINE_LENGTH},
+  store::STORE
+};
+
+use crate::{
+  info,
+  board::{BOARD_NAME, LED_COUNT, POWER_BUDGET},
+  common::{Events, EVENT_CHANNEL},
+  manager::schedule_save,
+  store::PRESETS
+};
+
+bind_interrupts!(struct Irqs {
+  USBCTRL_IRQ => InterruptHandler<USB>;
+});
+
+type UsbDriver = Driver<'static, USB>;
+
+const MAX_PACKET_SIZE: u16 = 64;
+
+// bytes of log output buffered while nobody is listening
+#[cfg(feature = "usb-log")]
+const USB_LOG_BUFFER_SIZE: usize = 1024;
+#[cfg(feature = "usb-log")]
+const USB_LOG_DEFAULT_LEVEL: log::LevelFilter = log::LevelFilter::Info;
+
+static INFO: LampInfo = LampInfo {
+  firmware: env!("CARGO_PKG_VERSION"),
+  board: BOARD_NAME,
+  led_count: LED_COUNT,
+  power_limit_ma: POWER_BUDGET.limit_ma,
+  usb_log: cfg!(feature = "usb-log")
+};
+
+// placeholders from the embassy examples; the project has no vid / pid of its own yet,
+// so these must be replaced before the lamp ships
+const USB_VID: u16 = 0xc0de;
+const USB_PID: u16 = 0xcafe;
+// all a port promises without negotiating, and what the desk budget counts on
+const USB_MAX_POWER_MA: u16 = 500;
+
+pub fn start_usb(spawner: Spawner, usb: USB) {
+  let driver = Driver::new(usb, Irqs);
+
+  let mut config = Config::new(USB_VID, USB_PID);
+  config.manufacturer = Some("Polis Interactive");
+  config.product = Some("Lamp");
+  config.max_power = USB_MAX_POWER_MA;
+  config.max_packet_size_0 = MAX_PACKET_SIZE as u8;
+  // lets windows pick up the serial port(s) of a composite device
+  config.device_class = 0xEF;
+  config.device_sub_class = 0x02;
+  config.device_protocol = 0x01;
+  config.composite_with_iads = true;
+
+  static CONFIG_DESCRIPTOR: StaticCell<[u8; 256]> = StaticCell::new();
+  static BOS_DESCRIPTOR: StaticCell<[u8; 256]> = StaticCell::new();
+  static CONTROL_BUF: StaticCell<[u8; 64]> = StaticCell::new();
+  let mut builder = Builder::new(
+    driver,
+    config,
+    CONFIG_DESCRIPTOR.init([0; 256]),
+    BOS_DESCRIPTOR.init([0; 256]),
+    &mut [],
+    CONTROL_BUF.init([0; 64]),
+  );
+
+  static CONSOLE_STATE: StaticCell<State> = StaticCell::new();
+  let console = CdcAcmClass::new(&mut builder, CONSOLE_STATE.init(State::new()), MAX_PACKET_SIZE);
+
+  // the logger shows up as a second serial port next to the console
+  #[cfg(feature = "usb-log")]
+  {
+    static LOGGER_STATE: StaticCell<State> = StaticCell::new();
+    let logger = CdcAcmClass::new(&mut builder, LOGGER_STATE.init(State::new()), MAX_PACKET_SIZE);
+    spawner.must_spawn(logger_task(logger));
+  }
+
+  spawner.must_spawn(usb_task(builder.build()));
+  spawner.must_spawn(console_task(console));
+}
+
+#[embassy_executor::task]
+async fn usb_task(mut usb: UsbDevice<'static, UsbDriver>) -> ! {
+  usb.run().await
+}
+
+#[cfg(feature = "usb-log")]
+#[embassy_executor::task]
+async fn logger_task(class: CdcAcmClass<'static, UsbDriver>) {
+  embassy_usb_logger::with_class!(USB_LOG_BUFFER_SIZE, USB_LOG_DEFAULT_LEVEL, class).await;
+}
+
+#[cfg(feature = "usb-log")]
+fn set_log_level(level: LogLevel) {
+  log::set_max_level(match level {
+    LogLevel::Off => log::LevelFilter::Off,
+    LogLevel::Error => log::LevelFilter::Error,
+    LogLevel::Warn => log::LevelFilter::Warn,
+    LogLevel::Info => log::LevelFilter::Info,
+    LogLevel::Debug => log::LevelFilter::Debug,
+    LogLevel::Trace => log::LevelFilter::Trace,
+  });
+}
+
+// the console refuses to change the level when usb-log is off
+#[cfg(not(feature = "usb-log"))]
+fn set_log_level(_level: LogLevel) {}
+
+#[embassy_executor::task]
+async fn console_task(mut class: CdcAcmClass<'static, UsbDriver>) {
+  loop {
+    class.wait_connection().await;
+    info!("Console connected");
+    let _ = run_console(&mut class).await;
+    info!("Console disconnected");
+  }
+}
+
+async fn run_console(class: &mut CdcAcmClass<'static, UsbDriver>) -> Result<(), EndpointError> {
+  let mut packet = [0; MAX_PACKET_SIZE as usize];
+  let mut line: Vec<u8, MAX_LINE_LENGTH> = Vec::new();
+  let mut overflowed = false;
+  loop {
+    let len = class.read_packet(&mut packet).await?;
+    for &byte in &packet[..len] {
+      if byte != b'\r' && byte != b'\n' {
+        // keep reading until the end of the line, but throw the whole thing away
+        overflowed |= line.push(byte).is_err();
+        continue;
+      }
+      if overflowed {
+        write_reply(class, "error: line too long").await?;
+      } else if !line.is_empty() {
+        run_line(class, &line).await?;
+      }
+      line.clear();
+      overflowed = false;
+    }
+  }
+}
+
+async fn run_line(class: &mut CdcAcmClass<'static, UsbDriver>, line: &[u8]) -> Result<(), EndpointError> {
+  let mut reply: String<256> = String::new();
+  let followup = match core::str::from_utf8(line) {
+    Ok(line) => handle_line(line, &STORE, &INFO, &mut reply).unwrap_or_else(|_| {
+      reply.clear();
+      let _ = reply.push_str("error: reply too long");
+      Followup::Nothing
+    }),
+    Err(_) => {
+      let _ = reply.push_str("error: not utf-8");
+      Followup::Nothing
+    }
+  };
+  let event = match followup {
+    Followup::Nothing => None,
+    Followup::ScheduleSave => {
+      schedule_save();
+      None
+    }
+    Followup::SaveNow => Some(Events::SaveStore),
+    Followup::Reset => Some(Events::Reset),
+    Followup::Undo => Some(Events::Undo),
+    Followup::SetLogLevel(level) => {
+      set_log_level(level);
+      None
+    }
+    Followup::ListPresets => {
+      let _ = PRESETS.lock(|presets| presets.borrow().write_list(&mut reply));
+      None
+    }
+    Followup::SavePreset(slot, name) => Some(Events::SavePreset(slot, name)),
+    Followup::RecallPreset(slot) => Some(Events::RecallPreset(slot)),
+  };
+  // waiting on a full channel would stall the console; let the user try again instead
+  if let Some(event) = event {
+    if EVENT_CHANNEL.try_send(event).is_err() {
+      reply.clear();
+      let _ = reply.push_str("error: lamp is busy, try again");
+    }
+  }
+  write_reply(class, &reply).await
+}
+
+async fn write_reply(class: &mut CdcAcmClass<'static, UsbDriver>, reply: &str) -> Result<(), EndpointError> {
+  for chunk in reply.as_bytes().chunks(MAX_PACKET_SIZE as usize) {
+    class.write_packet(chunk).await?;
+  }
+  // always a short packet, so it also ends the transfer
+  class.write_packet(b"\r\n").await
+}
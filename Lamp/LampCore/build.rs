@@ -0,0 +1,47 @@
+//! Generates the per-channel gamma tables used by the output stage.
+//!
+//! The exponents default to 2.8 (the curve the firmware has always shipped) and
+//! can be overridden per channel at build time with `LAMP_GAMMA_R`, `LAMP_GAMMA_G`,
+//! `LAMP_GAMMA_B` and `LAMP_GAMMA_W`, e.g. from the `[env]` table in
+//! `.cargo/config.toml`.
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+
+const DEFAULT_GAMMA: f64 = 2.8;
+
+fn exponent(var: &str) -> f64 {
+  println!("cargo:rerun-if-env-changed={}", var);
+  match env::var(var) {
+    Ok(raw) => {
+      let value: f64 = raw.trim().parse()
+        .unwrap_or_else(|_| panic!("{} must be a number, got {:?}", var, raw));
+      assert!(value > 0.0, "{} must be positive, got {}", var, value);
+      value
+    }
+    Err(_) => DEFAULT_GAMMA
+  }
+}
+
+fn table(name: &str, gamma: f64, out: &mut String) {
+  writeln!(out, "// gamma {}", gamma).unwrap();
+  write!(out, "const {}: [u8; 256] = [", name).unwrap();
+  for i in 0..256 {
+    let corrected = (i as f64 / 255.0).powf(gamma) * 255.0 + 0.5;
+    write!(out, "{},", corrected as u8).unwrap();
+  }
+  writeln!(out, "];").unwrap();
+}
+
+fn main() {
+  let mut out = String::new();
+  table("GAMMA_R", exponent("LAMP_GAMMA_R"), &mut out);
+  table("GAMMA_G", exponent("LAMP_GAMMA_G"), &mut out);
+  table("GAMMA_B", exponent("LAMP_GAMMA_B"), &mut out);
+  table("GAMMA_W", exponent("LAMP_GAMMA_W"), &mut out);
+  let path = PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("gamma.rs");
+  fs::write(path, out).unwrap();
+  println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,147 @@
+//! Draws every effect's golden frames next to what it renders now, as one png
+//! per effect: golden | current | difference, time running down, a column per led.
+//!
+//! usage: cargo run --example snapshots --features snapshot -- [out dir]   (defaults to target/snapshots)
+
+use std::{
+  fs,
+  io,
+  path::{Path, PathBuf}
+};
+
+use lamp_core::{
+  color::RGBA8,
+  effects::{EffectId, EFFECT_COUNT},
+  snapshot::{decode, render_snapshot, SnapshotFrame, SNAPSHOT_FRAMES, SNAPSHOT_LEDS}
+};
+
+// each led is this many pixels wide, with a dark gap between the panels
+const LED_WIDTH: usize = 8;
+const PANEL_GAP: usize = 4;
+// small differences are hard to see otherwise
+const DIFF_GAIN: u8 = 8;
+
+// the white channel shown as warm white on top of the rgb, like the simulator does
+fn to_rgb(led: &RGBA8) -> [u8; 3] {
+  let white = led.a as u16;
+  [
+    (led.r as u16 + white).min(255) as u8,
+    (led.g as u16 + white * 200 / 255).min(255) as u8,
+    (led.b as u16 + white * 140 / 255).min(255) as u8,
+  ]
+}
+
+fn diff(a: &RGBA8, b: &RGBA8) -> [u8; 3] {
+  let channel = |x: u8, y: u8| x.abs_diff(y).saturating_mul(DIFF_GAIN);
+  [channel(a.r, b.r).max(channel(a.a, b.a)), channel(a.g, b.g), channel(a.b, b.b)]
+}
+
+fn draw(golden: &[SnapshotFrame], current: &[SnapshotFrame]) -> (usize, usize, Vec<u8>) {
+  let panel = SNAPSHOT_LEDS * LED_WIDTH;
+  let width = panel * 3 + PANEL_GAP * 2;
+  let mut rgb = vec![0u8; width * SNAPSHOT_FRAMES * 3];
+  for (row, (golden, current)) in golden.iter().zip(current.iter()).enumerate() {
+    for led in 0..SNAPSHOT_LEDS {
+      let colors = [to_rgb(&golden[led]), to_rgb(&current[led]), diff(&golden[led], &current[led])];
+      for (index, color) in colors.iter().enumerate() {
+        let left = index * (panel + PANEL_GAP) + led * LED_WIDTH;
+        for x in left..left + LED_WIDTH {
+          let at = (row * width + x) * 3;
+          rgb[at..at + 3].copy_from_slice(color);
+        }
+      }
+    }
+  }
+  (width, SNAPSHOT_FRAMES, rgb)
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+  let mut crc = !0u32;
+  for byte in bytes {
+    crc ^= *byte as u32;
+    for _ in 0..8 {
+      crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+    }
+  }
+  !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+  let (mut a, mut b) = (1u32, 0u32);
+  for byte in bytes {
+    a = (a + *byte as u32) % 65_521;
+    b = (b + a) % 65_521;
+  }
+  (b << 16) | a
+}
+
+fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+  png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+  let start = png.len();
+  png.extend_from_slice(kind);
+  png.extend_from_slice(data);
+  let crc = crc32(&png[start..]);
+  png.extend_from_slice(&crc.to_be_bytes());
+}
+
+// uncompressed: stored deflate blocks are plenty for a thousand odd rows
+fn encode_png(width: usize, height: usize, rgb: &[u8]) -> Vec<u8> {
+  let mut raw = Vec::with_capacity((width * 3 + 1) * height);
+  for row in rgb.chunks(width * 3) {
+    raw.push(0);
+    raw.extend_from_slice(row);
+  }
+  let mut zlib = vec![0x78, 0x01];
+  let blocks = raw.chunks(u16::MAX as usize);
+  let count = blocks.len();
+  for (index, block) in blocks.enumerate() {
+    zlib.push((index + 1 == count) as u8);
+    zlib.extend_from_slice(&(block.len() as u16).to_le_bytes());
+    zlib.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+    zlib.extend_from_slice(block);
+  }
+  zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+  let mut header = Vec::new();
+  header.extend_from_slice(&(width as u32).to_be_bytes());
+  header.extend_from_slice(&(height as u32).to_be_bytes());
+  // 8 bit rgb, no interlacing
+  header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+  let mut png = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+  chunk(&mut png, b"IHDR", &header);
+  chunk(&mut png, b"IDAT", &zlib);
+  chunk(&mut png, b"IEND", &[]);
+  png
+}
+
+fn golden(id: u8) -> Option<Vec<SnapshotFrame>> {
+  let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("golden").join(format!("effect-{}.bin", id));
+  let data = fs::read(path).ok()?;
+  let mut frames = vec![[RGBA8::default(); SNAPSHOT_LEDS]; SNAPSHOT_FRAMES];
+  decode(&data, &mut frames).ok()?;
+  Some(frames)
+}
+
+fn main() -> io::Result<()> {
+  let out = std::env::args().nth(1).map_or_else(
+    || Path::new(env!("CARGO_MANIFEST_DIR")).join("target").join("snapshots"),
+    PathBuf::from
+  );
+  fs::create_dir_all(&out)?;
+  for id in 0..EFFECT_COUNT as u8 {
+    let mut current = vec![[RGBA8::default(); SNAPSHOT_LEDS]; SNAPSHOT_FRAMES];
+    render_snapshot(id.into(), &mut current);
+    let (golden, note) = match golden(id) {
+      Some(golden) if golden == current => (golden, "unchanged"),
+      Some(golden) => (golden, "CHANGED"),
+      // nothing to compare with; the difference panel shows the frames themselves
+      None => (vec![[RGBA8::default(); SNAPSHOT_LEDS]; SNAPSHOT_FRAMES], "no golden file"),
+    };
+    let (width, height, rgb) = draw(&golden, &current);
+    let path = out.join(format!("effect-{}.png", id));
+    fs::write(&path, encode_png(width, height, &rgb))?;
+    println!("{} {:?}: {} ({})", id, EffectId::from(id).kind(), path.display(), note);
+  }
+  Ok(())
+}
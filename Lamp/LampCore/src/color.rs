@@ -0,0 +1,269 @@
+
+use smart_leds::{hsv::{hsv2rgb, Hsv}, RGBA};
+
+use crate::gamma::Gamma;
+
+pub type RGBA8 = RGBA<u8>;
+
+// hue value with 36 colors + just warm white at 0
+pub const COLOR_STEPS: u8 = 37;
+pub const COLOR_MUL: u8 = 7;
+// could add 1 for this, but just do exclusive checks
+pub const COLOR_MAX: u8 =  COLOR_STEPS;
+
+// moves a 1/divisor of the way there, but at least 1; integer only, the rp2040 has no fpu
+pub fn eased_step(current: u8, target: u8, divisor: u8) -> u8 {
+  let delta = (target as i16) - (current as i16);
+  let step = (delta.unsigned_abs() / divisor.max(1) as u16).max(1) as u8;
+  match delta {
+    0 => target,
+    1.. => current.saturating_add(step),
+    _ => current.saturating_sub(step),
+  }
+}
+
+// derived from lib8tion
+
+pub const fn scale8(i: u8, scale: u8) -> u8 {
+  (((i as u16) * (1 + scale as u16)) >> 8) as u8
+}
+
+pub fn ease8_in_out_quad(i: u8) -> u8 {
+  let j = if i & 0x80 != 0 { 255 - i } else { i };
+  let jj2 = scale8(j, j) << 1;
+  if i & 0x80 != 0 { 255 - jj2 } else { jj2 }
+}
+
+// as it came from the firmware
+#[allow(clippy::needless_return)]
+pub fn lerp8(a: u8, b: u8, pct: u8) -> u8 {
+  if b > a {
+    let delta = b - a;
+    let scaled = scale8(delta, pct);
+    return a + scaled;
+  } else {
+    let delta = a - b;
+    let scaled = scale8(delta, pct);
+    return a - scaled;
+  }
+}
+
+// inverse of hsv2rgb, on the same 0..=255 hue wheel
+pub fn rgb2hsv(r: u8, g: u8, b: u8) -> Hsv {
+  let max = r.max(g).max(b);
+  let min = r.min(g).min(b);
+  let delta = (max - min) as i32;
+  if delta == 0 {
+    return Hsv { hue: 0, sat: 0, val: max };
+  }
+  let (r, g, b) = (r as i32, g as i32, b as i32);
+  // six sectors of 255 each, the one holding the max first
+  let sector = if max as i32 == r {
+    (g - b) * 255 / delta
+  } else if max as i32 == g {
+    2 * 255 + (b - r) * 255 / delta
+  } else {
+    4 * 255 + (r - g) * 255 / delta
+  };
+  Hsv {
+    hue: (sector.rem_euclid(6 * 255) * 256 / (6 * 255)) as u8,
+    sat: (delta * 255 / max as i32) as u8,
+    val: max
+  }
+}
+
+// blends around the hue wheel the short way instead of straight through rgb,
+// which would pass through gray between opposite hues. White blends on its own.
+pub fn hsv_lerp(from: &RGBA8, to: &RGBA8, pct: u8) -> RGBA8 {
+  match pct {
+    0 => return *from,
+    255 => return *to,
+    _ => {}
+  }
+  let mut start = rgb2hsv(from.r, from.g, from.b);
+  let mut end = rgb2hsv(to.r, to.g, to.b);
+  // black (warm white too, its rgb is off) has no hue or saturation and gray no hue;
+  // take the other side's so they don't spin
+  if start.val == 0 {
+    start = Hsv { val: 0, ..end };
+  } else if end.val == 0 {
+    end = Hsv { val: 0, ..start };
+  } else if start.sat == 0 {
+    start.hue = end.hue;
+  } else if end.sat == 0 {
+    end.hue = start.hue;
+  }
+  let turn = end.hue.wrapping_sub(start.hue) as i8 as i16;
+  let hue = (start.hue as i16 + turn * pct as i16 / 255) as u8;
+  let rgb = hsv2rgb(Hsv {
+    hue,
+    sat: lerp8(start.sat, end.sat, pct),
+    val: lerp8(start.val, end.val, pct)
+  });
+  RGBA8 { r: rgb.r, g: rgb.g, b: rgb.b, a: lerp8(from.a, to.a, pct) }
+}
+
+pub trait LampColor {
+  // sets the color from a step of the color wheel
+  #[allow(clippy::wrong_self_convention)]
+  fn from_u16(&mut self, value: u8);
+  fn lerp_from(&mut self, other: &RGBA8, pct: u8);
+  fn fade_from(&mut self, other: &RGBA8, pct: u8);
+  fn post_process(&mut self, other: &Self, pct: u8, gamma: &Gamma);
+  #[cfg(feature = "defmt")]
+  fn print_color(&self);
+}
+
+impl LampColor for RGBA8 {
+  fn from_u16(&mut self, value: u8) {
+    if value == 0 {
+      self.r = 0;
+      self.g = 0;
+      self.b = 0;
+      self.a = 255;
+      return;
+    }
+    // let (c, w) = (value - 1).div_rem(&WHITE_STEPS);
+    let c = (value - 1).wrapping_mul(COLOR_MUL);
+    let rgb =  hsv2rgb(Hsv {
+      hue: c,
+      sat: 255,
+      val: 255
+    });
+    self.r = rgb.r;
+    self.g = rgb.g;
+    self.b = rgb.b;
+    self.a = 0; // (w as u8).saturating_mul(WHITE_MUL);
+  }
+
+  fn lerp_from(&mut self, other: &Self, pct: u8) {
+    self.r = lerp8(other.r, self.r, pct);
+    self.g = lerp8(other.g, self.g, pct);
+    self.b = lerp8(other.b, self.b, pct);
+    self.a = lerp8(other.a, self.a, pct);
+  }
+
+  fn fade_from(&mut self, other: &Self, pct: u8) {
+    self.r = scale8(other.r, pct);
+    self.g = scale8(other.g, pct);
+    self.b = scale8(other.b, pct);
+    self.a = scale8(other.a, pct);
+  }
+
+  fn post_process(&mut self, other: &Self, pct: u8, gamma: &Gamma) {
+    self.r = gamma.r[scale8(other.r, pct) as usize];
+    self.g = gamma.g[scale8(other.g, pct) as usize];
+    self.b = gamma.b[scale8(other.b, pct) as usize];
+    self.a = gamma.w[scale8(other.a, pct) as usize];
+  }
+
+  #[cfg(feature = "defmt")]
+  fn print_color(&self) {
+    defmt::info!("RGBA(r {:?}, g {:?}, b {:?}, a {:?})", self.r, self.g, self.b, self.a);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn rgb2hsv_inverts_hsv2rgb() {
+    for hue in (0..=255u8).step_by(3) {
+      let rgb = hsv2rgb(Hsv { hue, sat: 255, val: 255 });
+      let hsv = rgb2hsv(rgb.r, rgb.g, rgb.b);
+      assert!((hsv.hue.wrapping_sub(hue) as i8).abs() <= 2, "hue {} came back as {}", hue, hsv.hue);
+      assert_eq!((hsv.sat, hsv.val), (255, 255));
+    }
+  }
+
+  #[test]
+  fn hsv_lerp_goes_around_the_wheel() {
+    let red = RGBA8 { r: 255, g: 0, b: 0, a: 0 };
+    let cyan = RGBA8 { r: 0, g: 255, b: 255, a: 0 };
+    assert_eq!(hsv_lerp(&red, &cyan, 0), red);
+    assert_eq!(hsv_lerp(&red, &cyan, 255), cyan);
+    // straight through rgb this would be a dim gray
+    let half = hsv_lerp(&red, &cyan, 128);
+    let hsv = rgb2hsv(half.r, half.g, half.b);
+    assert_eq!((hsv.sat, hsv.val), (255, 255));
+
+    // neighbouring wheel steps stay between their hues, including across the wrap
+    let (mut last, mut first) = (RGBA8::default(), RGBA8::default());
+    last.from_u16(COLOR_MAX - 1);
+    first.from_u16(1);
+    let half = hsv_lerp(&last, &first, 128);
+    let hue = rgb2hsv(half.r, half.g, half.b).hue;
+    assert!((COLOR_MUL * (COLOR_STEPS - 2)..=255).contains(&hue), "wrapped hue {}", hue);
+
+    // warm white only trades its white channel for the color
+    let mut white = RGBA8::default();
+    white.from_u16(0);
+    let half = hsv_lerp(&white, &red, 128);
+    assert_eq!((half.g, half.b), (0, 0));
+    assert!(half.r > 100 && half.a > 100);
+
+    // and heads straight for colors away from hue 0, without passing through red
+    for to in [cyan, RGBA8 { r: 0, g: 0, b: 255, a: 0 }] {
+      let target = rgb2hsv(to.r, to.g, to.b).hue;
+      for pct in [64, 128, 192] {
+        let step = hsv_lerp(&white, &to, pct);
+        let hue = rgb2hsv(step.r, step.g, step.b).hue;
+        assert!(hue.abs_diff(target) <= 1, "hue {} on the way to {} at {}", hue, target, pct);
+        assert_eq!(step.r, 0);
+      }
+    }
+  }
+
+  #[test]
+  fn eased_step_always_moves_and_never_overshoots() {
+    assert_eq!(eased_step(10, 10, 11), 10);
+    assert_eq!(eased_step(0, 5, 11), 1);
+    assert_eq!(eased_step(0, 110, 11), 10);
+    assert_eq!(eased_step(110, 0, 11), 100);
+    assert_eq!(eased_step(254, 255, 1), 255);
+  }
+
+  // what eased_step used to be, before it went integer only
+  fn eased_step_f32(current: u8, target: u8, factor: f32) -> u8 {
+    let delta = (target as i16) - (current as i16);
+    if delta == 0 {
+      return target;
+    }
+    let delta_abs = delta.abs() as f32;
+    let step = (delta_abs / factor).clamp(1.0, delta_abs) as u8;
+    if delta > 0 {
+      current.saturating_add(step)
+    } else {
+      current.saturating_sub(step)
+    }
+  }
+
+  #[test]
+  fn eased_step_matches_the_f32_curve() {
+    for divisor in [1u8, 3, 11, 16] {
+      for current in 0..=255u8 {
+        for target in 0..=255u8 {
+          assert_eq!(eased_step(current, target, divisor), eased_step_f32(current, target, divisor as f32));
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn lerp8_hits_both_ends() {
+    assert_eq!(lerp8(0, 255, 0), 0);
+    assert_eq!(lerp8(0, 255, 255), 255);
+    assert_eq!(lerp8(255, 0, 255), 0);
+    assert_eq!(lerp8(100, 100, 128), 100);
+  }
+
+  #[test]
+  fn zero_is_warm_white() {
+    let mut color = RGBA8::default();
+    color.from_u16(0);
+    assert_eq!(color, RGBA8 { r: 0, g: 0, b: 0, a: 255 });
+    color.from_u16(1);
+    assert_eq!(color.a, 0);
+  }
+}
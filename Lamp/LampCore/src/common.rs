@@ -0,0 +1,23 @@
+
+use heapless::String;
+
+use crate::{gesture::Gesture, presets::PRESET_NAME_LENGTH};
+
+#[derive(PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Events {
+  Button(Gesture),
+  // signed number of steps, already accelerated
+  EncoderTurn(i8),
+  ModeTimeout,
+  SaveStore,
+  // what a long press does, minus the countdown
+  Reset,
+  // back to the settings from before the last reset
+  Undo,
+  // an empty name keeps the slot's current one
+  SavePreset(u8, String<PRESET_NAME_LENGTH>),
+  // the preset made it to flash
+  PresetSaved(u8),
+  RecallPreset(u8)
+}
@@ -0,0 +1,376 @@
+
+//! Line based command protocol for the USB serial console.
+//!
+//!   get [brightness|color|effect]
+//!   set <brightness|color|effect> <value>
+//!   inc <brightness|color|effect>
+//!   dec <brightness|color|effect>
+//!   save
+//!   reset
+//!   undo
+//!   info
+//!   log <off|error|warn|info|debug|trace>
+//!   preset list
+//!   preset save <slot> [name]
+//!   preset recall <slot>
+//!   help
+//!
+//! `inc` / `dec` take the same steps as a turn of the encoder.
+
+use core::fmt::{self, Write};
+
+use heapless::String;
+
+use crate::{
+  effects::EffectId,
+  power::estimated_current_ma,
+  presets::{preset_name, PRESET_COUNT, PRESET_NAME_LENGTH},
+  render::frame_time_us,
+  store::AtomicStore
+};
+
+// longest line we accept; anything longer is dropped as a whole
+pub const MAX_LINE_LENGTH: usize = 64;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Field {
+  Brightness,
+  Color,
+  Effect
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LogLevel {
+  Off,
+  Error,
+  Warn,
+  Info,
+  Debug,
+  Trace
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Command<'a> {
+  Get(Option<Field>),
+  Set(Field, u8),
+  Step(Field, bool),
+  Save,
+  Reset,
+  Undo,
+  Info,
+  Log(LogLevel),
+  PresetList,
+  PresetSave(u8, &'a str),
+  PresetRecall(u8),
+  Help
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ParseError {
+  Empty,
+  UnknownCommand,
+  UnknownField,
+  UnknownLogLevel,
+  BadSlot,
+  MissingArgument,
+  BadValue,
+  TooManyArguments
+}
+
+impl ParseError {
+  fn describe(&self) -> &'static str {
+    match self {
+      ParseError::Empty => "empty line",
+      ParseError::UnknownCommand => "unknown command, try help",
+      ParseError::UnknownField => "unknown field, expected brightness, color or effect",
+      ParseError::UnknownLogLevel => "unknown log level, expected off, error, warn, info, debug or trace",
+      ParseError::BadSlot => "no such preset slot",
+      ParseError::MissingArgument => "missing argument",
+      ParseError::BadValue => "value must be 0-255",
+      ParseError::TooManyArguments => "too many arguments",
+    }
+  }
+}
+
+// what the caller still has to do after a command ran; the console itself only touches the store
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Followup {
+  Nothing,
+  // same debounced save an encoder turn schedules
+  ScheduleSave,
+  SaveNow,
+  // same as a long press, undoable
+  Reset,
+  // puts back the settings from before the last reset
+  Undo,
+  SetLogLevel(LogLevel),
+  // the preset table lives with the firmware, so it writes the reply itself
+  ListPresets,
+  SavePreset(u8, String<PRESET_NAME_LENGTH>),
+  RecallPreset(u8)
+}
+
+pub struct LampInfo {
+  pub firmware: &'static str,
+  pub board: &'static str,
+  pub led_count: usize,
+  pub power_limit_ma: u32,
+  // whether this build mirrors its logs over usb
+  pub usb_log: bool
+}
+
+fn parse_field(word: Option<&str>) -> Result<Field, ParseError> {
+  match word {
+    None => Err(ParseError::MissingArgument),
+    Some("brightness") => Ok(Field::Brightness),
+    Some("color") => Ok(Field::Color),
+    Some("effect") | Some("value") => Ok(Field::Effect),
+    Some(_) => Err(ParseError::UnknownField)
+  }
+}
+
+fn parse_log_level(word: Option<&str>) -> Result<LogLevel, ParseError> {
+  match word {
+    None => Err(ParseError::MissingArgument),
+    Some("off") => Ok(LogLevel::Off),
+    Some("error") => Ok(LogLevel::Error),
+    Some("warn") => Ok(LogLevel::Warn),
+    Some("info") => Ok(LogLevel::Info),
+    Some("debug") => Ok(LogLevel::Debug),
+    Some("trace") => Ok(LogLevel::Trace),
+    Some(_) => Err(ParseError::UnknownLogLevel)
+  }
+}
+
+fn parse_slot(word: Option<&str>) -> Result<u8, ParseError> {
+  let word = word.ok_or(ParseError::MissingArgument)?;
+  match word.parse() {
+    Ok(slot) if slot < PRESET_COUNT => Ok(slot),
+    _ => Err(ParseError::BadSlot)
+  }
+}
+
+fn parse_preset<'a>(mut words: impl Iterator<Item = &'a str>) -> Result<(Command<'a>, bool), ParseError> {
+  let command = match words.next() {
+    None => return Err(ParseError::MissingArgument),
+    Some("list") => Command::PresetList,
+    Some("save") => Command::PresetSave(parse_slot(words.next())?, words.next().unwrap_or("")),
+    Some("recall") => Command::PresetRecall(parse_slot(words.next())?),
+    Some(_) => return Err(ParseError::UnknownCommand)
+  };
+  Ok((command, words.next().is_some()))
+}
+
+pub fn parse(line: &str) -> Result<Command<'_>, ParseError> {
+  let mut words = line.split_whitespace();
+  let command = match words.next() {
+    None => return Err(ParseError::Empty),
+    Some("get") => match words.next() {
+      None => Command::Get(None),
+      field => Command::Get(Some(parse_field(field)?))
+    },
+    Some("set") => {
+      let field = parse_field(words.next())?;
+      let value = words.next().ok_or(ParseError::MissingArgument)?;
+      Command::Set(field, value.parse().map_err(|_| ParseError::BadValue)?)
+    },
+    Some("inc") => Command::Step(parse_field(words.next())?, true),
+    Some("dec") => Command::Step(parse_field(words.next())?, false),
+    Some("save") => Command::Save,
+    Some("reset") => Command::Reset,
+    Some("undo") => Command::Undo,
+    Some("info") => Command::Info,
+    Some("log") => Command::Log(parse_log_level(words.next())?),
+    Some("preset") => {
+      let (command, has_more) = parse_preset(&mut words)?;
+      if has_more {
+        return Err(ParseError::TooManyArguments);
+      }
+      command
+    }
+    Some("help") => Command::Help,
+    Some(_) => return Err(ParseError::UnknownCommand)
+  };
+  if words.next().is_some() {
+    return Err(ParseError::TooManyArguments);
+  }
+  Ok(command)
+}
+
+fn write_field(field: Field, store: &AtomicStore, reply: &mut impl Write) -> fmt::Result {
+  let settings = store.settings();
+  match field {
+    Field::Brightness => write!(reply, "brightness={}", settings.brightness),
+    Field::Color => write!(reply, "color={}", settings.color),
+    Field::Effect => {
+      write!(reply, "effect={} ({:?})", settings.value, EffectId::from(settings.value).kind())
+    }
+  }
+}
+
+pub fn execute(
+  command: Command<'_>,
+  store: &AtomicStore,
+  info: &LampInfo,
+  reply: &mut impl Write
+) -> Result<Followup, fmt::Error> {
+  match command {
+    Command::Get(Some(field)) => {
+      write_field(field, store, reply)?;
+      Ok(Followup::Nothing)
+    }
+    Command::Get(None) => {
+      write_field(Field::Brightness, store, reply)?;
+      reply.write_char(' ')?;
+      write_field(Field::Color, store, reply)?;
+      reply.write_char(' ')?;
+      write_field(Field::Effect, store, reply)?;
+      Ok(Followup::Nothing)
+    }
+    Command::Set(field, value) => {
+      match field {
+        Field::Brightness => store.set_brightness(value),
+        Field::Color => store.set_color(value),
+        Field::Effect => store.set_value(value),
+      }
+      write_field(field, store, reply)?;
+      Ok(Followup::ScheduleSave)
+    }
+    Command::Step(field, is_increment) => {
+      let delta = if is_increment { 1 } else { -1 };
+      match field {
+        Field::Brightness => store.update_brightness(delta),
+        Field::Color => store.update_color(delta),
+        Field::Effect => store.update_value(delta),
+      }
+      write_field(field, store, reply)?;
+      Ok(Followup::ScheduleSave)
+    }
+    Command::Save => {
+      reply.write_str("ok")?;
+      Ok(Followup::SaveNow)
+    }
+    Command::Reset => {
+      reply.write_str("ok")?;
+      Ok(Followup::Reset)
+    }
+    Command::Undo => {
+      reply.write_str("ok")?;
+      Ok(Followup::Undo)
+    }
+    Command::Info => {
+      write!(
+        reply,
+        "lamp firmware={} board={} leds={} power={}/{}mA frame={}us",
+        info.firmware, info.board, info.led_count, estimated_current_ma(), info.power_limit_ma, frame_time_us()
+      )?;
+      Ok(Followup::Nothing)
+    }
+    Command::Log(_) if !info.usb_log => {
+      reply.write_str("error: usb logging is not enabled in this build")?;
+      Ok(Followup::Nothing)
+    }
+    Command::Log(level) => {
+      write!(reply, "log={:?}", level)?;
+      Ok(Followup::SetLogLevel(level))
+    }
+    Command::PresetList => Ok(Followup::ListPresets),
+    Command::PresetSave(slot, name) => {
+      // the manager writes it to flash later, and that can still fail
+      write!(reply, "saving preset {}", slot)?;
+      Ok(Followup::SavePreset(slot, preset_name(name)))
+    }
+    Command::PresetRecall(slot) => {
+      write!(reply, "recalling preset {}", slot)?;
+      Ok(Followup::RecallPreset(slot))
+    }
+    Command::Help => {
+      reply.write_str(
+        "get [field] | set <field> <0-255> | inc <field> | dec <field> | save | reset | undo | info | log <level> | \
+        preset <list | save <slot> [name] | recall <slot>>; fields: brightness color effect"
+      )?;
+      Ok(Followup::Nothing)
+    }
+  }
+}
+
+// parses and runs one line, writing either the result or the error into reply
+pub fn handle_line(
+  line: &str,
+  store: &AtomicStore,
+  info: &LampInfo,
+  reply: &mut impl Write
+) -> Result<Followup, fmt::Error> {
+  match parse(line) {
+    Ok(command) => execute(command, store, info, reply),
+    Err(e) => {
+      write!(reply, "error: {}", e.describe())?;
+      Ok(Followup::Nothing)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use crate::{color::COLOR_MAX, store::BRIGHTNESS_INCREMENT};
+
+  const INFO: LampInfo = LampInfo { firmware: "0.1.0", board: "desk", led_count: 5, power_limit_ma: 500, usb_log: true };
+
+  fn run(line: &str, store: &AtomicStore) -> (String<256>, Followup) {
+    let mut reply = String::new();
+    let followup = handle_line(line, store, &INFO, &mut reply).unwrap();
+    (reply, followup)
+  }
+
+  #[test]
+  fn parses_commands() {
+    assert_eq!(parse("get"), Ok(Command::Get(None)));
+    assert_eq!(parse("  set color 12 "), Ok(Command::Set(Field::Color, 12)));
+    assert_eq!(parse("dec effect"), Ok(Command::Step(Field::Effect, false)));
+    assert_eq!(parse(""), Err(ParseError::Empty));
+    assert_eq!(parse("set color"), Err(ParseError::MissingArgument));
+    assert_eq!(parse("set color 300"), Err(ParseError::BadValue));
+    assert_eq!(parse("set hue 3"), Err(ParseError::UnknownField));
+    assert_eq!(parse("save now"), Err(ParseError::TooManyArguments));
+    assert_eq!(parse("log loud"), Err(ParseError::UnknownLogLevel));
+    assert_eq!(parse("preset save 1 reading"), Ok(Command::PresetSave(1, "reading")));
+    assert_eq!(parse("preset recall 9"), Err(ParseError::BadSlot));
+    assert_eq!(parse("preset list all"), Err(ParseError::TooManyArguments));
+    assert_eq!(parse("explode"), Err(ParseError::UnknownCommand));
+  }
+
+  #[test]
+  fn steps_like_the_encoder() {
+    let store = AtomicStore::new();
+    let (reply, followup) = run("dec brightness", &store);
+    assert_eq!(reply.as_str(), "brightness=239");
+    assert_eq!(store.settings().brightness, 255 - BRIGHTNESS_INCREMENT);
+    assert_eq!(followup, Followup::ScheduleSave);
+    run("dec color", &store);
+    assert_eq!(store.settings().color, COLOR_MAX);
+  }
+
+  #[test]
+  fn sets_and_reports() {
+    let store = AtomicStore::new();
+    run("set color 200", &store);
+    let (reply, followup) = run("get", &store);
+    assert_eq!(followup, Followup::Nothing);
+    assert!(reply.starts_with("brightness=255 color=37 effect=0"));
+    assert_eq!(run("save", &store).1, Followup::SaveNow);
+    assert_eq!(run("reset", &store).1, Followup::Reset);
+    assert_eq!(run("undo", &store).1, Followup::Undo);
+    assert!(run("info", &store).0.starts_with("lamp firmware=0.1.0 board=desk leds=5 power="));
+    assert_eq!(run("log debug", &store).1, Followup::SetLogLevel(LogLevel::Debug));
+    assert_eq!(run("preset recall 2", &store).1, Followup::RecallPreset(2));
+    let (reply, followup) = run("preset save 0 evening", &store);
+    assert_eq!((reply.as_str(), followup), ("saving preset 0", Followup::SavePreset(0, "evening".try_into().unwrap())));
+    assert!(run("nope", &store).0.starts_with("error:"));
+  }
+}
@@ -0,0 +1,39 @@
+
+use embassy_time::{Duration, Instant};
+use rand::Rng;
+
+use crate::color::{ease8_in_out_quad, lerp8, LampColor, RGBA8};
+
+use super::Effect;
+
+const BREATH_PERIOD: Duration = Duration::from_millis(6_000);
+// never fully out, so the lamp still reads as on at the bottom of a breath
+const BREATH_MIN_VALUE: u8 = 24;
+
+// the whole lamp slowly swells and fades in unison
+pub struct Breathing {
+  started: Instant
+}
+
+impl Breathing {
+  pub fn new(now: Instant) -> Self {
+    Self { started: now }
+  }
+}
+
+impl<const N: usize> Effect<N> for Breathing {
+  fn start(&mut self, now: Instant, _rng: &mut impl Rng) {
+    self.started = now;
+  }
+
+  fn render(&mut self, now: Instant, color: &RGBA8, data: &mut [RGBA8; N], _rng: &mut impl Rng) {
+    let period = BREATH_PERIOD.as_millis();
+    // 0..512 over one breath, folded into a 0..=255..=0 triangle
+    let phase = (now.saturating_duration_since(self.started).as_millis() % period) * 512 / period;
+    let triangle = (if phase < 256 { phase } else { 511 - phase }) as u8;
+    let pct = lerp8(BREATH_MIN_VALUE, 255, ease8_in_out_quad(triangle));
+    for led in data.iter_mut() {
+      led.fade_from(color, pct);
+    }
+  }
+}
@@ -0,0 +1,60 @@
+
+use embassy_time::{Duration, Instant};
+use rand::Rng;
+
+use crate::{
+  color::{LampColor, RGBA8},
+  transition::{Easing, Tween}
+};
+
+use super::Effect;
+
+const CANDLE_MIN_VALUE: u8 = 96;
+const CANDLE_MAX_VALUE: u8 = 255;
+const CANDLE_MIN_FLICKER_TIME: Duration = Duration::from_millis(40);
+const CANDLE_MAX_FLICKER_TIME: Duration = Duration::from_millis(180);
+// how long a pixel takes to settle on a new level; the curve covers a third of the way
+// in the first 10 ms, like the per tick easing it replaced
+const CANDLE_EASE_TIME: Duration = Duration::from_millis(170);
+
+// every pixel wanders between random levels on its own short timer
+pub struct Candle<const N: usize> {
+  values: [u8; N],
+  targets: [u8; N],
+  tweens: [Tween<u8>; N],
+  next_flicker: [Instant; N]
+}
+
+impl<const N: usize> Candle<N> {
+  pub fn new(now: Instant) -> Self {
+    Self {
+      values: [CANDLE_MAX_VALUE; N],
+      targets: [CANDLE_MAX_VALUE; N],
+      tweens: [Tween::new(CANDLE_MAX_VALUE, CANDLE_EASE_TIME, Easing::Exponential); N],
+      next_flicker: [now; N]
+    }
+  }
+}
+
+impl<const N: usize> Effect<N> for Candle<N> {
+  fn start(&mut self, now: Instant, _rng: &mut impl Rng) {
+    self.next_flicker = [now; N];
+  }
+
+  fn render(&mut self, now: Instant, color: &RGBA8, data: &mut [RGBA8; N], rng: &mut impl Rng) {
+    let leds = self.values.iter_mut().zip(self.targets.iter_mut()).zip(self.tweens.iter_mut());
+    for (((value, target), tween), (next_flicker, led)) in leds.zip(self.next_flicker.iter_mut().zip(data.iter_mut())) {
+      // every flicker that came due since the last frame, each at its own time, so
+      // the curve is the same however often we're asked to render
+      while now >= *next_flicker {
+        *target = rng.gen_range(CANDLE_MIN_VALUE..=CANDLE_MAX_VALUE);
+        tween.step_since(value, target, *next_flicker, now);
+        *next_flicker += Duration::from_millis(rng.gen_range(
+          CANDLE_MIN_FLICKER_TIME.as_millis()..=CANDLE_MAX_FLICKER_TIME.as_millis()
+        ));
+      }
+      tween.step(value, target, now);
+      led.fade_from(color, *value);
+    }
+  }
+}
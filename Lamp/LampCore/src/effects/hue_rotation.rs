@@ -0,0 +1,46 @@
+
+use embassy_time::{Duration, Instant};
+use rand::Rng;
+use smart_leds::hsv::{hsv2rgb, Hsv};
+
+use crate::color::RGBA8;
+
+use super::Effect;
+
+// time for the wheel to come all the way around
+const HUE_ROTATION_PERIOD: Duration = Duration::from_millis(30_000);
+// distance between neighbouring pixels on the wheel
+const HUE_ROTATION_SPREAD: u8 = 24;
+
+// a slowly turning rainbow; the wheel is the color, so the selected one is ignored
+pub struct HueRotation {
+  started: Instant
+}
+
+impl HueRotation {
+  pub fn new(now: Instant) -> Self {
+    Self { started: now }
+  }
+}
+
+impl<const N: usize> Effect<N> for HueRotation {
+  fn start(&mut self, now: Instant, _rng: &mut impl Rng) {
+    self.started = now;
+  }
+
+  fn render(&mut self, now: Instant, _color: &RGBA8, data: &mut [RGBA8; N], _rng: &mut impl Rng) {
+    let period = HUE_ROTATION_PERIOD.as_millis();
+    let base_hue = ((now.saturating_duration_since(self.started).as_millis() % period) * 256 / period) as u8;
+    for (idx, led) in data.iter_mut().enumerate() {
+      let rgb = hsv2rgb(Hsv {
+        hue: base_hue.wrapping_add((idx as u8).wrapping_mul(HUE_ROTATION_SPREAD)),
+        sat: 255,
+        val: 255
+      });
+      led.r = rgb.r;
+      led.g = rgb.g;
+      led.b = rgb.b;
+      led.a = 0;
+    }
+  }
+}
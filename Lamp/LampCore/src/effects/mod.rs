@@ -0,0 +1,196 @@
+
+use embassy_time::Instant;
+use rand::Rng;
+
+use crate::{
+  color::RGBA8,
+  walker::{WalkerEffect, WalkerIntensity}
+};
+
+mod breathing;
+pub use breathing::Breathing;
+
+mod candle;
+pub use candle::Candle;
+
+mod hue_rotation;
+pub use hue_rotation::HueRotation;
+
+mod sparkle;
+pub use sparkle::Sparkle;
+
+// an animation that renders N pixels in the selected color
+pub trait Effect<const N: usize> {
+  // called whenever the effect is (re)selected, so it can pick a fresh starting point
+  fn start(&mut self, now: Instant, rng: &mut impl Rng);
+  fn render(&mut self, now: Instant, color: &RGBA8, data: &mut [RGBA8; N], rng: &mut impl Rng);
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum EffectKind {
+  Walker(WalkerIntensity),
+  Breathing,
+  Candle,
+  HueRotation,
+  Sparkle
+}
+
+pub const EFFECT_COUNT: usize = 7;
+pub const EFFECT_MAX: u8 = (EFFECT_COUNT - 1) as u8;
+
+// the index into this list is what gets persisted, so only ever append to it
+pub const EFFECTS: [EffectKind; EFFECT_COUNT] = [
+  EffectKind::Walker(WalkerIntensity::new(0)),
+  EffectKind::Walker(WalkerIntensity::new(1)),
+  EffectKind::Walker(WalkerIntensity::new(2)),
+  EffectKind::Breathing,
+  EffectKind::Candle,
+  EffectKind::HueRotation,
+  EffectKind::Sparkle,
+];
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct EffectId(u8);
+
+impl EffectId {
+  pub fn kind(&self) -> EffectKind {
+    EFFECTS[self.0 as usize]
+  }
+}
+
+impl From<u8> for EffectId {
+  fn from(item: u8) -> Self {
+    Self(item.min(EFFECT_MAX))
+  }
+}
+
+#[derive(PartialEq)]
+pub struct EffectSetting {
+  pub effect: EffectId,
+  pub pct: u8
+}
+
+// enum dispatch, so the registry needs neither an allocator nor trait objects
+pub enum Effects<const N: usize> {
+  Walker(WalkerEffect<N>),
+  Breathing(Breathing),
+  Candle(Candle<N>),
+  HueRotation(HueRotation),
+  Sparkle(Sparkle<N>)
+}
+
+impl<const N: usize> Effects<N> {
+  pub fn new(effect: EffectId, now: Instant, rng: &mut impl Rng) -> Self {
+    let mut out = match effect.kind() {
+      EffectKind::Walker(intensity) => Effects::Walker(WalkerEffect::new(intensity)),
+      EffectKind::Breathing => Effects::Breathing(Breathing::new(now)),
+      EffectKind::Candle => Effects::Candle(Candle::new(now)),
+      EffectKind::HueRotation => Effects::HueRotation(HueRotation::new(now)),
+      EffectKind::Sparkle => Effects::Sparkle(Sparkle::new(now)),
+    };
+    out.start(now, rng);
+    out
+  }
+}
+
+impl<const N: usize> Effect<N> for Effects<N> {
+  fn start(&mut self, now: Instant, rng: &mut impl Rng) {
+    match self {
+      Effects::Walker(effect) => effect.start(now, rng),
+      Effects::Breathing(effect) => Effect::<N>::start(effect, now, rng),
+      Effects::Candle(effect) => effect.start(now, rng),
+      Effects::HueRotation(effect) => Effect::<N>::start(effect, now, rng),
+      Effects::Sparkle(effect) => effect.start(now, rng),
+    }
+  }
+
+  fn render(&mut self, now: Instant, color: &RGBA8, data: &mut [RGBA8; N], rng: &mut impl Rng) {
+    match self {
+      Effects::Walker(effect) => effect.render(now, color, data, rng),
+      Effects::Breathing(effect) => effect.render(now, color, data, rng),
+      Effects::Candle(effect) => effect.render(now, color, data, rng),
+      Effects::HueRotation(effect) => effect.render(now, color, data, rng),
+      Effects::Sparkle(effect) => effect.render(now, color, data, rng),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  extern crate std;
+
+  use super::*;
+  use embassy_time::Duration;
+  use rand::{rngs::SmallRng, SeedableRng};
+
+  use crate::rng::LampRng;
+
+  #[test]
+  fn ids_clamp_to_the_registry() {
+    assert_eq!(EffectId::from(0).kind(), EffectKind::Walker(WalkerIntensity::new(0)));
+    assert_eq!(EffectId::from(200).kind(), EFFECTS[EFFECT_COUNT - 1]);
+  }
+
+  #[test]
+  fn every_effect_renders_in_the_selected_color() {
+    let mut rng = SmallRng::seed_from_u64(0);
+    let red = RGBA8 { r: 255, g: 0, b: 0, a: 0 };
+    let start = Instant::from_millis(0);
+    for id in 0..EFFECT_COUNT as u8 {
+      let mut effect = Effects::<8>::new(id.into(), start, &mut rng);
+      let mut data = [RGBA8::default(); 8];
+      for frame in 0..500 {
+        effect.render(start + Duration::from_millis(frame * 10), &red, &mut data, &mut rng);
+      }
+      // hue rotation brings its own colors; everything else only scales the one it was given
+      if EffectId::from(id).kind() != EffectKind::HueRotation {
+        assert!(data.iter().all(|led| led.g == 0 && led.b == 0 && led.a == 0));
+      }
+    }
+  }
+
+  #[test]
+  fn same_seed_and_timestamps_render_the_same_frames() {
+    let color = RGBA8 { r: 200, g: 100, b: 0, a: 0 };
+    let start = Instant::from_millis(5_000);
+    for id in 0..EFFECT_COUNT as u8 {
+      let runs: [[[RGBA8; 6]; 40]; 2] = core::array::from_fn(|_| {
+        let mut rng = LampRng::new(42);
+        let mut effect = Effects::<6>::new(id.into(), start, &mut rng);
+        core::array::from_fn(|frame| {
+          let mut data = [RGBA8::default(); 6];
+          // uneven steps, like a ticker that sometimes runs late
+          effect.render(start + Duration::from_millis(frame as u64 * 97), &color, &mut data, &mut rng);
+          data
+        })
+      });
+      assert_eq!(runs[0], runs[1], "effect {} differs between runs", id);
+    }
+  }
+
+  // a single pixel, so both runs draw from the rng in the same order
+  fn candle_frames(tick: u64, frames: u64) -> std::vec::Vec<(u64, RGBA8)> {
+    let color = RGBA8 { r: 255, g: 120, b: 0, a: 0 };
+    let start = Instant::from_millis(0);
+    let mut rng = LampRng::new(7);
+    let mut candle = Candle::<1>::new(start);
+    (0..frames)
+      .map(|frame| {
+        let mut data = [RGBA8::default(); 1];
+        candle.render(start + Duration::from_millis(frame * tick), &color, &mut data, &mut rng);
+        (frame * tick, data[0])
+      })
+      .collect()
+  }
+
+  #[test]
+  fn candle_flickers_the_same_at_any_tick_rate() {
+    let fast = candle_frames(5, 400);
+    let slow = candle_frames(10, 200);
+    for frame in &slow {
+      assert!(fast.contains(frame), "{:?} differs at 5 ms", frame);
+    }
+    // and it did flicker
+    assert!(slow.iter().any(|(_, led)| *led != slow[0].1));
+  }
+}
@@ -0,0 +1,50 @@
+
+use embassy_time::{Duration, Instant};
+use rand::Rng;
+
+use crate::color::{LampColor, RGBA8};
+
+use super::Effect;
+
+// level the pixels rest at between sparks
+const SPARKLE_BASE_VALUE: u8 = 64;
+// average time between sparks on a single pixel
+const SPARKLE_MEAN_GAP_TIME: Duration = Duration::from_millis(2_500);
+// time for a spark to fall from full back to the base level
+const SPARKLE_FADE_TIME: Duration = Duration::from_millis(600);
+
+// pixels sit dim and randomly flash to full, decaying back down
+pub struct Sparkle<const N: usize> {
+  values: [u8; N],
+  last_time: Instant
+}
+
+impl<const N: usize> Sparkle<N> {
+  pub fn new(now: Instant) -> Self {
+    Self {
+      values: [SPARKLE_BASE_VALUE; N],
+      last_time: now
+    }
+  }
+}
+
+impl<const N: usize> Effect<N> for Sparkle<N> {
+  fn start(&mut self, now: Instant, _rng: &mut impl Rng) {
+    self.values = [SPARKLE_BASE_VALUE; N];
+    self.last_time = now;
+  }
+
+  fn render(&mut self, now: Instant, color: &RGBA8, data: &mut [RGBA8; N], rng: &mut impl Rng) {
+    let elapsed = now.saturating_duration_since(self.last_time).as_millis();
+    self.last_time = now;
+    let decay = (elapsed * 255 / SPARKLE_FADE_TIME.as_millis()).min(255) as u8;
+    for (value, led) in self.values.iter_mut().zip(data.iter_mut()) {
+      *value = value.saturating_sub(decay).max(SPARKLE_BASE_VALUE);
+      // chance of a spark grows with the time since the last frame
+      if rng.gen_range(0..SPARKLE_MEAN_GAP_TIME.as_millis()) < elapsed {
+        *value = 255;
+      }
+      led.fade_from(color, *value);
+    }
+  }
+}
@@ -0,0 +1,100 @@
+
+use embassy_time::{Duration, Instant};
+use portable_atomic::{AtomicI16, Ordering};
+
+// detents closer together than the interval move the setting by the multiplier instead
+// of one step; fastest first
+pub const ENCODER_ACCELERATION: [(u64, u8); 3] = [(15, 6), (30, 3), (60, 2)];
+
+// turns detents into signed steps, larger the faster the knob spins
+#[derive(Default, Debug)]
+pub struct Acceleration {
+  last_detent: Option<(Instant, bool)>,
+}
+
+impl Acceleration {
+  pub const fn new() -> Self {
+    Self { last_detent: None }
+  }
+
+  pub fn step(&mut self, now: Instant, is_increment: bool) -> i8 {
+    let multiplier = match self.last_detent {
+      // a change of direction always starts slow
+      Some((last, was_increment)) if was_increment == is_increment => {
+        let interval = now.saturating_duration_since(last);
+        ENCODER_ACCELERATION
+          .iter()
+          .find(|(max_interval, _)| interval < Duration::from_millis(*max_interval))
+          .map_or(1, |(_, multiplier)| *multiplier)
+      }
+      _ => 1
+    };
+    self.last_detent = Some((now, is_increment));
+    if is_increment { multiplier as i8 } else { -(multiplier as i8) }
+  }
+}
+
+// detents the encoder has seen but the manager hasn't applied yet; lets the encoder
+// keep counting without waiting on the manager, e.g. while it's writing to flash
+#[derive(Default, Debug)]
+pub struct TurnAccumulator {
+  delta: AtomicI16,
+}
+
+impl TurnAccumulator {
+  pub const fn new() -> Self {
+    Self { delta: AtomicI16::new(0) }
+  }
+
+  pub fn add(&self, delta: i8) {
+    let _ = self.delta.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |current| {
+      Some(current.saturating_add(delta as i16))
+    });
+  }
+
+  // takes at most one i8 worth of steps; anything past that stays for the next call
+  pub fn take(&self) -> i8 {
+    let mut taken = 0;
+    let _ = self.delta.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |current| {
+      taken = current.clamp(i8::MIN as i16, i8::MAX as i16);
+      Some(current - taken)
+    });
+    taken as i8
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.delta.load(Ordering::Relaxed) == 0
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn speeds_up_with_fast_detents() {
+    let mut acceleration = Acceleration::new();
+    assert_eq!(acceleration.step(Instant::from_millis(0), true), 1);
+    assert_eq!(acceleration.step(Instant::from_millis(200), true), 1);
+    assert_eq!(acceleration.step(Instant::from_millis(250), true), 2);
+    assert_eq!(acceleration.step(Instant::from_millis(260), true), 6);
+    // reversing resets, even when fast
+    assert_eq!(acceleration.step(Instant::from_millis(265), false), -1);
+    assert_eq!(acceleration.step(Instant::from_millis(285), false), -3);
+  }
+
+  #[test]
+  fn accumulates_until_taken() {
+    let turns = TurnAccumulator::new();
+    turns.add(3);
+    turns.add(-1);
+    assert_eq!(turns.take(), 2);
+    assert!(turns.is_empty());
+    for _ in 0..30 {
+      turns.add(6);
+    }
+    assert_eq!(turns.take(), 127);
+    assert_eq!(turns.take(), 53);
+    assert!(turns.is_empty());
+  }
+}
@@ -0,0 +1,41 @@
+
+// tables are generated by build.rs from the LAMP_GAMMA_* exponents
+include!(concat!(env!("OUT_DIR"), "/gamma.rs"));
+
+// one curve per output channel; the white led does not track the rgb ones
+pub struct Gamma {
+  pub r: [u8; 256],
+  pub g: [u8; 256],
+  pub b: [u8; 256],
+  pub w: [u8; 256],
+}
+
+pub static GAMMA: Gamma = Gamma {
+  r: GAMMA_R,
+  g: GAMMA_G,
+  b: GAMMA_B,
+  w: GAMMA_W,
+};
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn curves_are_monotonic_and_span_the_range() {
+    for curve in [&GAMMA.r, &GAMMA.g, &GAMMA.b, &GAMMA.w] {
+      assert_eq!(curve[0], 0);
+      assert_eq!(curve[255], 255);
+      assert!(curve.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+  }
+
+  #[test]
+  fn default_curve_matches_the_old_table() {
+    // with the default exponents; spot checks against the hand pasted GAMMA8 this replaced
+    assert_eq!(GAMMA.r[28], 1);
+    assert_eq!(GAMMA.g[128], 37);
+    assert_eq!(GAMMA.b[200], 129);
+    assert_eq!(GAMMA.w[254], 252);
+  }
+}
@@ -0,0 +1,208 @@
+
+//! Turns raw button edges (and encoder turns while the button is down) into gestures.
+//!
+//! A click is only reported once CLICK_GAP_IN_MILISECONDS pass without another press,
+//! since until then it could still become a double or triple click.
+
+use embassy_time::{Duration, Instant};
+
+pub const CLICK_GAP_IN_MILISECONDS: u64 = 300;
+pub const LONG_PRESS_IN_MILISECONDS: u64 = 2000;
+// long enough that it can't happen by accident; see the reset countdown in overlay
+pub const VERY_LONG_PRESS_IN_MILISECONDS: u64 = 10_000;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Gesture {
+  Click,
+  DoubleClick,
+  TripleClick,
+  // reported while the button is still down, as soon as the hold is long enough
+  LongPress,
+  // follows a LongPress if the button is held even longer
+  VeryLongPress,
+  // a single encoder step (+1 / -1) while the button is down; the release afterwards is swallowed
+  HoldTurn(i8)
+}
+
+#[derive(Clone, Copy, Debug)]
+enum State {
+  Idle,
+  Pressed {
+    since: Instant,
+    turned: bool,
+    // how many of LongPress / VeryLongPress went out for this hold
+    held: u8
+  },
+  Released {
+    at: Instant
+  }
+}
+
+#[derive(Debug)]
+pub struct GestureRecognizer {
+  state: State,
+  clicks: u8,
+}
+
+impl GestureRecognizer {
+  pub const fn new() -> Self {
+    Self { state: State::Idle, clicks: 0 }
+  }
+
+  pub fn is_pressed(&self) -> bool {
+    matches!(self.state, State::Pressed { .. })
+  }
+
+  // down, and held long enough to have reported a LongPress
+  pub fn is_long_press(&self) -> bool {
+    matches!(self.state, State::Pressed { held: 1.., .. })
+  }
+
+  pub fn press(&mut self, now: Instant) -> Option<Gesture> {
+    if !self.is_pressed() {
+      self.state = State::Pressed { since: now, turned: false, held: 0 };
+    }
+    None
+  }
+
+  pub fn release(&mut self, now: Instant) -> Option<Gesture> {
+    let State::Pressed { turned, held, .. } = self.state else {
+      return None;
+    };
+    if turned || held > 0 {
+      self.state = State::Idle;
+      self.clicks = 0;
+      return None;
+    }
+    self.clicks += 1;
+    if self.clicks >= 3 {
+      self.state = State::Idle;
+      self.clicks = 0;
+      return Some(Gesture::TripleClick);
+    }
+    self.state = State::Released { at: now };
+    None
+  }
+
+  // only claims the turn while the button is down; otherwise it's a plain encoder turn
+  pub fn turn(&mut self, is_increment: bool) -> Option<Gesture> {
+    let State::Pressed { turned, .. } = &mut self.state else {
+      return None;
+    };
+    *turned = true;
+    self.clicks = 0;
+    Some(Gesture::HoldTurn(if is_increment { 1 } else { -1 }))
+  }
+
+  // when poll has something to report next, if ever
+  pub fn deadline(&self) -> Option<Instant> {
+    match self.state {
+      State::Idle => None,
+      State::Pressed { turned: true, .. } => None,
+      State::Pressed { since, held: 0, .. } => Some(since + Duration::from_millis(LONG_PRESS_IN_MILISECONDS)),
+      State::Pressed { since, held: 1, .. } => Some(since + Duration::from_millis(VERY_LONG_PRESS_IN_MILISECONDS)),
+      State::Pressed { .. } => None,
+      State::Released { at } => Some(at + Duration::from_millis(CLICK_GAP_IN_MILISECONDS)),
+    }
+  }
+
+  pub fn poll(&mut self, now: Instant) -> Option<Gesture> {
+    if self.deadline().is_none_or(|deadline| now < deadline) {
+      return None;
+    }
+    match &mut self.state {
+      State::Pressed { held, .. } => {
+        *held += 1;
+        self.clicks = 0;
+        if *held == 1 { Some(Gesture::LongPress) } else { Some(Gesture::VeryLongPress) }
+      }
+      State::Released { .. } => {
+        let clicks = self.clicks;
+        self.state = State::Idle;
+        self.clicks = 0;
+        match clicks {
+          1 => Some(Gesture::Click),
+          _ => Some(Gesture::DoubleClick)
+        }
+      }
+      State::Idle => None
+    }
+  }
+}
+
+impl Default for GestureRecognizer {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn at(ms: u64) -> Instant {
+    Instant::from_millis(ms)
+  }
+
+  // presses for `down` ms at `start`, then polls until the recognizer settles
+  fn click(recognizer: &mut GestureRecognizer, start: u64, down: u64) -> Option<Gesture> {
+    recognizer.press(at(start));
+    recognizer.poll(at(start + down)).or(recognizer.release(at(start + down)))
+  }
+
+  fn settle(recognizer: &mut GestureRecognizer) -> Option<Gesture> {
+    let deadline = recognizer.deadline()?;
+    recognizer.poll(deadline)
+  }
+
+  #[test]
+  fn counts_clicks() {
+    let mut recognizer = GestureRecognizer::new();
+    assert_eq!(click(&mut recognizer, 0, 80), None);
+    // not yet; a second press could still come
+    assert_eq!(recognizer.poll(at(200)), None);
+    assert_eq!(settle(&mut recognizer), Some(Gesture::Click));
+
+    assert_eq!(click(&mut recognizer, 1000, 80), None);
+    assert_eq!(click(&mut recognizer, 1200, 80), None);
+    assert_eq!(settle(&mut recognizer), Some(Gesture::DoubleClick));
+
+    assert_eq!(click(&mut recognizer, 2000, 80), None);
+    assert_eq!(click(&mut recognizer, 2200, 80), None);
+    // the third click can't be followed by anything, so it goes out right away
+    assert_eq!(click(&mut recognizer, 2400, 80), Some(Gesture::TripleClick));
+    assert_eq!(recognizer.deadline(), None);
+  }
+
+  #[test]
+  fn long_and_very_long_press_fire_while_held() {
+    let mut recognizer = GestureRecognizer::new();
+    recognizer.press(at(0));
+    assert_eq!(recognizer.poll(at(LONG_PRESS_IN_MILISECONDS - 1)), None);
+    assert!(!recognizer.is_long_press());
+    assert_eq!(settle(&mut recognizer), Some(Gesture::LongPress));
+    assert!(recognizer.is_long_press());
+    assert_eq!(settle(&mut recognizer), Some(Gesture::VeryLongPress));
+    assert_eq!(recognizer.deadline(), None);
+    // letting go afterwards isn't a click
+    assert_eq!(recognizer.release(at(VERY_LONG_PRESS_IN_MILISECONDS + 500)), None);
+    assert_eq!(recognizer.deadline(), None);
+    // so a LongPress handled after this is stale, and so is one handled during the next short press
+    assert!(!recognizer.is_long_press());
+    recognizer.press(at(VERY_LONG_PRESS_IN_MILISECONDS + 600));
+    assert!(!recognizer.is_long_press());
+  }
+
+  #[test]
+  fn turning_while_held_swallows_the_press() {
+    let mut recognizer = GestureRecognizer::new();
+    assert_eq!(recognizer.turn(true), None);
+    recognizer.press(at(0));
+    assert_eq!(recognizer.turn(false), Some(Gesture::HoldTurn(-1)));
+    // no long press for a hold that turned
+    assert_eq!(recognizer.deadline(), None);
+    assert_eq!(recognizer.release(at(3000)), None);
+    assert_eq!(recognizer.deadline(), None);
+  }
+}
@@ -0,0 +1,96 @@
+
+//! The settings as they were before each reset, newest last, so a reset can be
+//! undone. Persisted under its own key, encoded like the store.
+
+use heapless::Vec;
+use serde::{Deserialize, Serialize};
+
+use crate::persist::{DecodeError, Settings};
+
+pub const HISTORY_KEY: u8 = 2;
+pub const HISTORY_DEPTH: usize = 4;
+// how long after a reset a double click undoes it instead of toggling power
+pub const UNDO_WINDOW_IN_SECONDS: u64 = 10;
+
+pub const HISTORY_VERSION: u8 = 1;
+pub const HISTORY_BUFFER_SIZE: usize = 32;
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Default, Debug)]
+pub struct ResetHistory {
+  entries: Vec<Settings, HISTORY_DEPTH>,
+}
+
+impl ResetHistory {
+  pub const fn new() -> Self {
+    Self { entries: Vec::new() }
+  }
+
+  // drops the oldest entry once full
+  pub fn push(&mut self, settings: Settings) {
+    if self.entries.is_full() {
+      self.entries.remove(0);
+    }
+    let _ = self.entries.push(settings);
+  }
+
+  pub fn pop(&mut self) -> Option<Settings> {
+    self.entries.pop()
+  }
+
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+}
+
+pub fn encode<'a>(history: &ResetHistory, buffer: &'a mut [u8]) -> Result<&'a [u8], postcard::Error> {
+  let (version, body) = buffer.split_first_mut().ok_or(postcard::Error::SerializeBufferFull)?;
+  *version = HISTORY_VERSION;
+  let len = postcard::to_slice(history, body)?.len();
+  Ok(&buffer[..len + 1])
+}
+
+pub fn decode(data: &[u8]) -> Result<ResetHistory, DecodeError> {
+  let (version, body) = data.split_first().ok_or(DecodeError::Empty)?;
+  match *version {
+    1 => postcard::from_bytes::<ResetHistory>(body).map_err(|_| DecodeError::Corrupt),
+    other => Err(DecodeError::UnknownVersion(other))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn settings(brightness: u8) -> Settings {
+    Settings { brightness, ..Settings::default() }
+  }
+
+  #[test]
+  fn keeps_the_newest_entries() {
+    let mut history = ResetHistory::new();
+    for brightness in 0..6 {
+      history.push(settings(brightness));
+    }
+    assert_eq!(history.len(), HISTORY_DEPTH);
+    assert_eq!(history.pop(), Some(settings(5)));
+    assert_eq!(history.pop(), Some(settings(4)));
+    assert_eq!(history.pop(), Some(settings(3)));
+    assert_eq!(history.pop(), Some(settings(2)));
+    assert_eq!(history.pop(), None);
+  }
+
+  #[test]
+  fn round_trips_a_full_history() {
+    let mut history = ResetHistory::new();
+    for brightness in 0..HISTORY_DEPTH as u8 {
+      history.push(Settings { brightness, color: 255, value: 255 });
+    }
+    let mut buffer = [0; HISTORY_BUFFER_SIZE];
+    let encoded = encode(&history, &mut buffer).unwrap();
+    assert_eq!(decode(encoded), Ok(history));
+  }
+}
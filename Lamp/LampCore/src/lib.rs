@@ -0,0 +1,46 @@
+#![no_std]
+
+//! Hardware independent lamp logic; the firmware in `LampCode` wraps these
+//! in embassy tasks, everything here also builds and tests on the host.
+
+pub mod common;
+pub use common::Events;
+
+pub mod color;
+
+pub mod gamma;
+
+pub mod transition;
+
+pub mod power;
+
+pub mod effects;
+
+pub mod walker;
+
+pub mod gesture;
+
+pub mod encoder;
+
+pub mod store;
+
+pub mod persist;
+
+pub mod presets;
+
+pub mod history;
+
+pub mod console;
+
+pub mod manager;
+
+pub mod overlay;
+
+pub mod render;
+
+pub mod output;
+
+pub mod rng;
+
+#[cfg(any(test, feature = "snapshot"))]
+pub mod snapshot;
@@ -0,0 +1,548 @@
+
+use embassy_futures::select::{select, Either};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel, signal::Signal};
+use embassy_time::{Duration, Instant, Timer};
+use heapless::{String, Vec};
+
+use crate::{
+  common::Events,
+  gesture::Gesture,
+  history::UNDO_WINDOW_IN_SECONDS,
+  presets::{SavedSlots, PRESET_COUNT, PRESET_NAME_LENGTH}
+};
+
+// 3.5 minutes, meh
+pub const MODE_TIMEOUT_IN_SECONDS: u64 = 60 * 3 + 30;
+pub const SAVE_TIMEOUT_IN_MILISECONDS: u64 = 1000;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ManagerStates {
+  Brightness,
+  Value,
+  Color
+}
+
+pub fn transition_manager_state(current_state: ManagerStates) -> ManagerStates {
+  match current_state {
+    ManagerStates::Brightness => {
+      ManagerStates::Color
+    }
+    ManagerStates::Color => {
+      ManagerStates::Value
+    }
+    ManagerStates::Value => {
+      ManagerStates::Brightness
+    }
+  }
+}
+
+// what the manager asks of the rest of the lamp; the firmware runs these against
+// its tasks, the flash and the store, the simulator against its deadlines
+#[derive(PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Command {
+  StartModeTimeout,
+  // only restarts a timeout that is already running
+  ResetModeTimeout,
+  StopModeTimeout,
+  // debounced by SAVE_TIMEOUT_IN_MILISECONDS
+  ScheduleSave,
+  WriteStore,
+  ShowMode(ManagerStates),
+  StartCountdown,
+  CancelCountdown,
+  UpdateBrightness(i8),
+  NudgeBrightness(i8),
+  UpdateColor(i8),
+  UpdateValue(i8),
+  TogglePower,
+  // remember the settings for undo, then reset them
+  ResetStore,
+  UndoReset,
+  ClearPresets,
+  SavePreset(u8, String<PRESET_NAME_LENGTH>),
+  RecallPreset(u8),
+}
+
+// the most any one event asks for
+pub const MAX_COMMANDS: usize = 5;
+pub type Commands = Vec<Command, MAX_COMMANDS>;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ManagerState {
+  pub mode: ManagerStates,
+  // the slot a triple click recalls after
+  pub last_preset: Option<u8>,
+  pub saved_presets: SavedSlots,
+  // a double click before this undoes the last reset instead of toggling power
+  pub undo_deadline: Option<Instant>,
+  // reset on the very long press instead of the long one
+  pub long_hold_reset: bool,
+}
+
+impl ManagerState {
+  pub const fn new(saved_presets: SavedSlots, long_hold_reset: bool) -> Self {
+    Self {
+      mode: ManagerStates::Brightness,
+      last_preset: None,
+      saved_presets,
+      undo_deadline: None,
+      long_hold_reset,
+    }
+  }
+
+  fn reset(&mut self, now: Instant, commands: &mut Commands) {
+    self.mode = ManagerStates::Brightness;
+    self.undo_deadline = Some(now + Duration::from_secs(UNDO_WINDOW_IN_SECONDS));
+    push(commands, [Command::StopModeTimeout, Command::ScheduleSave, Command::ResetStore]);
+  }
+
+  fn adjust(&self, delta: i8, fine: bool, commands: &mut Commands) {
+    let update = match self.mode {
+      ManagerStates::Brightness if fine => Command::NudgeBrightness(delta),
+      ManagerStates::Brightness => Command::UpdateBrightness(delta),
+      ManagerStates::Value => Command::UpdateValue(delta),
+      ManagerStates::Color => Command::UpdateColor(delta),
+    };
+    push(commands, [Command::ResetModeTimeout, Command::ScheduleSave, update]);
+  }
+
+  // only counts as saved once the write comes back as Events::PresetSaved
+  fn preset_saved(&mut self, slot: u8) {
+    // the firmware reports a slot that doesn't exist; there's nothing to remember
+    if slot < PRESET_COUNT {
+      self.saved_presets.insert(slot);
+      self.last_preset = Some(slot);
+    }
+  }
+
+  fn recall_preset(&mut self, slot: u8, commands: &mut Commands) {
+    if !self.saved_presets.contains(slot) {
+      return;
+    }
+    self.last_preset = Some(slot);
+    // the recalled scene is the new normal, so it is what comes back after a restart
+    push(commands, [Command::RecallPreset(slot), Command::ScheduleSave]);
+  }
+}
+
+fn push<const C: usize>(commands: &mut Commands, new: [Command; C]) {
+  for command in new {
+    // MAX_COMMANDS covers every arm of reduce
+    let _ = commands.push(command);
+  }
+}
+
+// the whole interaction model, with nothing but the state and the event to go on
+pub fn reduce(mut state: ManagerState, event: Events, now: Instant) -> (ManagerState, Commands) {
+  let mut commands = Commands::new();
+  match event {
+    Events::SaveStore => push(&mut commands, [Command::WriteStore]),
+    Events::ModeTimeout => {
+      state.mode = ManagerStates::Brightness;
+      push(&mut commands, [Command::ShowMode(state.mode)]);
+    }
+    Events::Button(Gesture::LongPress) => {
+      push(&mut commands, [Command::StartCountdown]);
+      // with long_hold_reset, a long press only starts the countdown
+      if !state.long_hold_reset {
+        state.reset(now, &mut commands);
+      }
+    }
+    Events::Reset => state.reset(now, &mut commands),
+    // factory reset: the settings (still undoable) and every preset
+    Events::Button(Gesture::VeryLongPress) => {
+      push(&mut commands, [Command::CancelCountdown]);
+      if state.long_hold_reset {
+        state.reset(now, &mut commands);
+      }
+      push(&mut commands, [Command::ClearPresets]);
+      state.saved_presets = SavedSlots::new();
+      state.last_preset = None;
+    }
+    Events::Button(Gesture::Click) => {
+      state.mode = transition_manager_state(state.mode);
+      push(&mut commands, [Command::StartModeTimeout, Command::ShowMode(state.mode)]);
+    }
+    Events::Button(Gesture::DoubleClick) if state.undo_deadline.is_some_and(|deadline| now < deadline) => {
+      state.undo_deadline = None;
+      push(&mut commands, [Command::UndoReset]);
+    }
+    Events::Button(Gesture::DoubleClick) => push(&mut commands, [Command::ScheduleSave, Command::TogglePower]),
+    Events::Undo => {
+      state.undo_deadline = None;
+      push(&mut commands, [Command::UndoReset]);
+    }
+    // fine adjustment; only brightness has steps smaller than a turn
+    Events::Button(Gesture::HoldTurn(delta)) => state.adjust(delta, true, &mut commands),
+    // while adjusting color or effect, store the scene; otherwise step through the saved ones
+    Events::Button(Gesture::TripleClick) if state.mode != ManagerStates::Brightness => {
+      state.mode = ManagerStates::Brightness;
+      push(&mut commands, [Command::StopModeTimeout, Command::ShowMode(state.mode)]);
+      // never over a saved one; once they're all taken, overwriting needs a slot from the console
+      if let Some(slot) = state.saved_presets.first_free() {
+        push(&mut commands, [Command::SavePreset(slot, String::new())]);
+      }
+    }
+    Events::Button(Gesture::TripleClick) => {
+      if let Some(slot) = state.saved_presets.next_after(state.last_preset) {
+        state.recall_preset(slot, &mut commands);
+      }
+    }
+    Events::SavePreset(slot, name) => push(&mut commands, [Command::SavePreset(slot, name)]),
+    Events::PresetSaved(slot) => state.preset_saved(slot),
+    Events::RecallPreset(slot) => state.recall_preset(slot, &mut commands),
+    Events::EncoderTurn(delta) => state.adjust(delta, false, &mut commands),
+  }
+  (state, commands)
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ModeCommands {
+  Start,
+  Reset,
+  Stop
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SaveCommands {
+  Save,
+}
+
+pub type ModeSignal = Signal<CriticalSectionRawMutex, ModeCommands>;
+pub type SaveSignal = Signal<CriticalSectionRawMutex, SaveCommands>;
+// the queue the manager reads its events from
+pub type EventChannel<const C: usize> = Channel<CriticalSectionRawMutex, Events, C>;
+
+// the next command, or None once the deadline has passed
+pub async fn wait_until<T: Send>(
+  signal: &Signal<CriticalSectionRawMutex, T>,
+  deadline: Option<Instant>
+) -> Option<T> {
+  let Some(deadline) = deadline else {
+    return Some(signal.wait().await);
+  };
+  match select(Timer::at(deadline), signal.wait()).await {
+    Either::First(_) => None,
+    Either::Second(command) => Some(command),
+  }
+}
+
+// the body of the firmware's mode_timeout_task; here so the host can run it against a mock clock
+pub async fn run_mode_timeout<const C: usize>(signal: &ModeSignal, events: &EventChannel<C>) {
+  let timeout = Duration::from_secs(MODE_TIMEOUT_IN_SECONDS);
+  let mut deadline: Option<Instant> = None;
+  loop {
+    match wait_until(signal, deadline).await {
+      // only a button push starts the timer
+      Some(ModeCommands::Start) => deadline = Some(Instant::now() + timeout),
+      // user has taken some action; keep waiting for the timeout
+      Some(ModeCommands::Reset) => {
+        if deadline.is_some() {
+          deadline = Some(Instant::now() + timeout);
+        }
+      }
+      // manager already reset state
+      Some(ModeCommands::Stop) => deadline = None,
+      None => {
+        deadline = None;
+        events.send(Events::ModeTimeout).await;
+      }
+    }
+  }
+}
+
+// the body of save_task: one save, SAVE_TIMEOUT_IN_MILISECONDS after the last request
+pub async fn run_save_debounce<const C: usize>(signal: &SaveSignal, events: &EventChannel<C>) {
+  let timeout = Duration::from_millis(SAVE_TIMEOUT_IN_MILISECONDS);
+  let mut deadline: Option<Instant> = None;
+  loop {
+    match wait_until(signal, deadline).await {
+      Some(SaveCommands::Save) => deadline = Some(Instant::now() + timeout),
+      None => {
+        deadline = None;
+        events.send(Events::SaveStore).await;
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  extern crate std;
+
+  use std::sync::mpsc;
+
+  use embassy_executor::{Executor, Spawner};
+  use embassy_futures::yield_now;
+  use embassy_time::MockDriver;
+
+  use super::*;
+
+  #[test]
+  fn short_press_cycles_through_every_state() {
+    let mut state = ManagerStates::Brightness;
+    state = transition_manager_state(state);
+    assert_eq!(state, ManagerStates::Color);
+    state = transition_manager_state(state);
+    assert_eq!(state, ManagerStates::Value);
+    state = transition_manager_state(state);
+    assert_eq!(state, ManagerStates::Brightness);
+  }
+
+  const START: ManagerState = ManagerState::new(SavedSlots::new(), false);
+
+  fn at(millis: u64) -> Instant {
+    Instant::from_millis(millis)
+  }
+
+  fn run(state: ManagerState, events: impl IntoIterator<Item = Events>) -> (ManagerState, std::vec::Vec<Command>) {
+    let mut state = state;
+    let mut all = std::vec::Vec::new();
+    for event in events {
+      let (next, commands) = reduce(state, event, at(0));
+      state = next;
+      all.extend(commands);
+    }
+    (state, all)
+  }
+
+  fn every_event() -> impl Iterator<Item = Events> {
+    let gestures = [
+      Gesture::Click, Gesture::DoubleClick, Gesture::TripleClick, Gesture::LongPress,
+      Gesture::VeryLongPress, Gesture::HoldTurn(-2), Gesture::HoldTurn(3)
+    ];
+    gestures.into_iter().map(Events::Button).chain([
+      Events::EncoderTurn(1), Events::EncoderTurn(-4), Events::ModeTimeout, Events::SaveStore,
+      Events::Reset, Events::Undo, Events::SavePreset(1, String::new()), Events::SavePreset(PRESET_COUNT, String::new()),
+      Events::PresetSaved(2), Events::PresetSaved(PRESET_COUNT), Events::RecallPreset(1), Events::RecallPreset(2)
+    ])
+  }
+
+  #[test]
+  fn every_event_from_every_state() {
+    let modes = [ManagerStates::Brightness, ManagerStates::Color, ManagerStates::Value];
+    for mode in modes {
+      for long_hold_reset in [false, true] {
+        let mut saved = SavedSlots::new();
+        saved.insert(1);
+        let state = ManagerState { mode, last_preset: Some(1), saved_presets: saved, undo_deadline: None, long_hold_reset };
+        for event in every_event() {
+          let turn = matches!(event, Events::EncoderTurn(_) | Events::Button(Gesture::HoldTurn(_)));
+          let back_to_brightness = matches!(event, Events::ModeTimeout | Events::Reset);
+          let (next, commands) = reduce(state, event, at(0));
+          // turning only ever changes the setting the mode points at
+          if turn {
+            assert_eq!(next, state);
+            assert!(commands.contains(&Command::ScheduleSave));
+          }
+          if back_to_brightness {
+            assert_eq!(next.mode, ManagerStates::Brightness);
+          }
+          // a change of mode is always shown, unless it comes from a reset
+          if next.mode != mode && !commands.contains(&Command::ResetStore) {
+            assert!(commands.contains(&Command::ShowMode(next.mode)));
+          }
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn turns_follow_the_mode() {
+    let (state, commands) = run(START, [Events::EncoderTurn(2), Events::Button(Gesture::HoldTurn(-1))]);
+    assert_eq!(state.mode, ManagerStates::Brightness);
+    assert!(commands.contains(&Command::UpdateBrightness(2)));
+    assert!(commands.contains(&Command::NudgeBrightness(-1)));
+    let (state, commands) = run(START, [Events::Button(Gesture::Click), Events::EncoderTurn(1)]);
+    assert_eq!(state.mode, ManagerStates::Color);
+    assert!(commands.contains(&Command::StartModeTimeout));
+    assert!(commands.contains(&Command::UpdateColor(1)));
+    let (_, commands) = run(state, [Events::Button(Gesture::Click), Events::Button(Gesture::HoldTurn(1))]);
+    assert!(commands.contains(&Command::UpdateValue(1)));
+  }
+
+  #[test]
+  fn times_out_back_to_brightness() {
+    let (state, _) = run(START, [Events::Button(Gesture::Click), Events::Button(Gesture::Click)]);
+    assert_eq!(state.mode, ManagerStates::Value);
+    let (state, commands) = reduce(state, Events::ModeTimeout, at(0));
+    assert_eq!(state.mode, ManagerStates::Brightness);
+    assert_eq!(commands.as_slice(), &[Command::ShowMode(ManagerStates::Brightness)]);
+  }
+
+  #[test]
+  fn long_press_resets_and_double_click_undoes_in_time() {
+    let (state, _) = run(START, [Events::Button(Gesture::Click)]);
+    let (state, commands) = reduce(state, Events::Button(Gesture::LongPress), at(1_000));
+    assert_eq!(state.mode, ManagerStates::Brightness);
+    assert_eq!(commands.as_slice(), &[
+      Command::StartCountdown, Command::StopModeTimeout, Command::ScheduleSave, Command::ResetStore
+    ]);
+    let window = Duration::from_secs(UNDO_WINDOW_IN_SECONDS).as_millis();
+    let (undone, commands) = reduce(state, Events::Button(Gesture::DoubleClick), at(1_000 + window - 1));
+    assert_eq!(commands.as_slice(), &[Command::UndoReset]);
+    assert_eq!(undone.undo_deadline, None);
+    // too late, it's a power toggle again
+    let (_, commands) = reduce(state, Events::Button(Gesture::DoubleClick), at(1_000 + window));
+    assert_eq!(commands.as_slice(), &[Command::ScheduleSave, Command::TogglePower]);
+
+    // with long_hold_reset only the very long press resets, and it also clears the presets
+    let held = ManagerState::new(SavedSlots::new(), true);
+    let (_, commands) = reduce(held, Events::Button(Gesture::LongPress), at(0));
+    assert_eq!(commands.as_slice(), &[Command::StartCountdown]);
+    let (_, commands) = reduce(held, Events::Button(Gesture::VeryLongPress), at(0));
+    assert!(commands.contains(&Command::ResetStore) && commands.contains(&Command::ClearPresets));
+  }
+
+  #[test]
+  fn triple_click_saves_then_cycles_presets() {
+    // nothing saved yet, nothing to recall
+    let (state, commands) = run(START, [Events::Button(Gesture::TripleClick)]);
+    assert!(commands.is_empty());
+    let (state, commands) = run(state, [Events::Button(Gesture::Click), Events::Button(Gesture::TripleClick)]);
+    assert_eq!(state.mode, ManagerStates::Brightness);
+    assert!(commands.contains(&Command::SavePreset(0, String::new())));
+    // not saved until the write says so
+    assert_eq!(state.saved_presets, SavedSlots::new());
+    let (state, _) = run(state, [Events::PresetSaved(0)]);
+    let (state, commands) = run(state, [Events::SavePreset(2, "night".try_into().unwrap())]);
+    assert_eq!(commands.as_slice(), &[Command::SavePreset(2, "night".try_into().unwrap())]);
+    assert_eq!(state.last_preset, Some(0));
+    let (state, _) = run(state, [Events::PresetSaved(2)]);
+    assert_eq!(state.last_preset, Some(2));
+    // the next triple click saves into the first free slot, not over the last one
+    let (state, commands) = run(state, [Events::Button(Gesture::Click), Events::Button(Gesture::TripleClick)]);
+    assert!(commands.contains(&Command::SavePreset(1, String::new())));
+    let (state, _) = run(state, [Events::PresetSaved(1), Events::PresetSaved(3)]);
+    // all full: nothing gets overwritten
+    let (state, commands) = run(state, [Events::Button(Gesture::Click), Events::Button(Gesture::TripleClick)]);
+    assert!(!commands.iter().any(|command| matches!(command, Command::SavePreset(..))));
+    let (state, commands) = run(state, [Events::Button(Gesture::TripleClick)]);
+    assert_eq!(commands.as_slice(), &[Command::RecallPreset(0), Command::ScheduleSave]);
+    let (state, commands) = run(state, [Events::Button(Gesture::TripleClick)]);
+    assert_eq!(commands.as_slice(), &[Command::RecallPreset(1), Command::ScheduleSave]);
+    // a factory reset forgets them
+    let (state, _) = run(state, [Events::Button(Gesture::VeryLongPress)]);
+    assert_eq!(state.saved_presets, SavedSlots::new());
+    assert!(run(state, [Events::RecallPreset(2)]).1.is_empty());
+  }
+
+  // a handful of events is all any of these scripts expect
+  type TestChannel = EventChannel<4>;
+  type Sent = std::vec::Vec<(u64, Events)>;
+
+  // small enough to be cheap, and every deadline here lands on one
+  const STEP_IN_MILISECONDS: u64 = 10;
+
+  // the mock clock is global, so tests that move it take turns
+  static CLOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+  // spawns the tasks on embassy's std executor; it never returns, so it gets a thread of its
+  // own and the script reports back what it saw
+  fn on_executor(spawn: fn(Spawner, mpsc::Sender<Sent>)) -> Sent {
+    let _clock = CLOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let (report, result) = mpsc::channel();
+    std::thread::spawn(move || {
+      let executor = std::boxed::Box::leak(std::boxed::Box::new(Executor::new()));
+      executor.run(|spawner| spawn(spawner, report))
+    });
+    result.recv_timeout(std::time::Duration::from_secs(30)).expect("the script never finished")
+  }
+
+  // lets every woken task run before the script looks at the channel
+  async fn settle() {
+    for _ in 0..4 {
+      yield_now().await;
+    }
+  }
+
+  // signals each command at its time, stepping the mock clock until `until`, and notes when
+  // each event comes out
+  async fn play<T: Copy + Send>(
+    signal: &Signal<CriticalSectionRawMutex, T>,
+    events: &TestChannel,
+    script: &[(u64, T)],
+    until: u64
+  ) -> Sent {
+    let mut sent = Sent::new();
+    let mut script = script.iter().peekable();
+    let mut elapsed = 0;
+    while elapsed <= until {
+      while let Some((_, command)) = script.next_if(|(at, _)| *at == elapsed) {
+        signal.signal(*command);
+        settle().await;
+      }
+      while let Ok(event) = events.try_receive() {
+        sent.push((elapsed, event));
+      }
+      MockDriver::get().advance(Duration::from_millis(STEP_IN_MILISECONDS));
+      settle().await;
+      elapsed += STEP_IN_MILISECONDS;
+    }
+    sent
+  }
+
+  static MODE_SIGNAL: ModeSignal = Signal::new();
+  static MODE_EVENTS: TestChannel = Channel::new();
+
+  #[embassy_executor::task]
+  async fn mode_timeout_task() {
+    run_mode_timeout(&MODE_SIGNAL, &MODE_EVENTS).await
+  }
+
+  #[embassy_executor::task]
+  async fn mode_script(report: mpsc::Sender<Sent>) {
+    let script = [
+      // a turn pushes the timeout back
+      (0, ModeCommands::Start), (60_000, ModeCommands::Reset),
+      // a turn without a running timeout doesn't start one
+      (400_000, ModeCommands::Reset),
+      // stopped before it runs out
+      (700_000, ModeCommands::Start), (800_000, ModeCommands::Stop),
+    ];
+    let _ = report.send(play(&MODE_SIGNAL, &MODE_EVENTS, &script, 1_000_000).await);
+  }
+
+  #[test]
+  fn mode_timeout_fires_after_the_last_turn() {
+    let sent = on_executor(|spawner, report| {
+      spawner.must_spawn(mode_timeout_task());
+      spawner.must_spawn(mode_script(report));
+    });
+    let timeout = Duration::from_secs(MODE_TIMEOUT_IN_SECONDS).as_millis();
+    assert_eq!(sent, [(60_000 + timeout, Events::ModeTimeout)]);
+  }
+
+  static SAVE_SIGNAL: SaveSignal = Signal::new();
+  static SAVE_EVENTS: TestChannel = Channel::new();
+
+  #[embassy_executor::task]
+  async fn save_task() {
+    run_save_debounce(&SAVE_SIGNAL, &SAVE_EVENTS).await
+  }
+
+  #[embassy_executor::task]
+  async fn save_script(report: mpsc::Sender<Sent>) {
+    let script = [
+      // a burst of changes is saved once, after the last of them
+      (0, SaveCommands::Save), (500, SaveCommands::Save), (900, SaveCommands::Save),
+      (5_000, SaveCommands::Save),
+    ];
+    let _ = report.send(play(&SAVE_SIGNAL, &SAVE_EVENTS, &script, 10_000).await);
+  }
+
+  #[test]
+  fn save_is_debounced() {
+    let sent = on_executor(|spawner, report| {
+      spawner.must_spawn(save_task());
+      spawner.must_spawn(save_script(report));
+    });
+    let debounce = SAVE_TIMEOUT_IN_MILISECONDS;
+    assert_eq!(sent, [(900 + debounce, Events::SaveStore), (5_000 + debounce, Events::SaveStore)]);
+  }
+}
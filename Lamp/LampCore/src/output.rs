@@ -0,0 +1,108 @@
+
+//! Where finished frames go. The firmware implements this for the pio
+//! ws2812 / sk6812 strip and for pwm driven analog leds; the recorder keeps
+//! frames in memory for tests and the simulator.
+
+use core::future::{ready, Future};
+
+use heapless::Deque;
+
+use crate::color::RGBA8;
+
+pub trait LedOutput<const N: usize> {
+  // the led supply, and its indicator if the board has one
+  fn set_enabled(&mut self, enabled: bool);
+  fn write(&mut self, frame: &[RGBA8; N]) -> impl Future<Output = ()>;
+}
+
+// duty for each channel of a single analog led, 0..=top: the strip's average,
+// so a frame meant for several pixels still comes out at the same brightness
+pub fn analog_duty(frame: &[RGBA8], top: u16) -> [u16; 4] {
+  let mut sums = [0u32; 4];
+  for led in frame.iter() {
+    for (sum, channel) in sums.iter_mut().zip([led.r, led.g, led.b, led.a]) {
+      *sum += channel as u32;
+    }
+  }
+  let full = 255 * frame.len().max(1) as u32;
+  sums.map(|sum| (sum * top as u32 / full) as u16)
+}
+
+// keeps the last DEPTH frames, oldest first
+#[derive(Default, Debug)]
+pub struct Recorder<const N: usize, const DEPTH: usize> {
+  enabled: bool,
+  written: u32,
+  frames: Deque<[RGBA8; N], DEPTH>,
+}
+
+impl<const N: usize, const DEPTH: usize> Recorder<N, DEPTH> {
+  pub const fn new() -> Self {
+    Self { enabled: false, written: 0, frames: Deque::new() }
+  }
+
+  pub fn record(&mut self, frame: &[RGBA8; N]) {
+    if self.frames.is_full() {
+      self.frames.pop_front();
+    }
+    let _ = self.frames.push_back(*frame);
+    self.written += 1;
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    self.enabled
+  }
+
+  // every frame ever written, not just the ones still kept
+  pub fn written(&self) -> u32 {
+    self.written
+  }
+
+  pub fn last(&self) -> Option<&[RGBA8; N]> {
+    self.frames.back()
+  }
+
+  pub fn frames(&self) -> impl Iterator<Item = &[RGBA8; N]> {
+    self.frames.iter()
+  }
+}
+
+impl<const N: usize, const DEPTH: usize> LedOutput<N> for Recorder<N, DEPTH> {
+  fn set_enabled(&mut self, enabled: bool) {
+    self.enabled = enabled;
+  }
+
+  fn write(&mut self, frame: &[RGBA8; N]) -> impl Future<Output = ()> {
+    self.record(frame);
+    ready(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn recorder_keeps_the_latest_frames() {
+    let mut recorder = Recorder::<2, 3>::new();
+    assert!(!recorder.is_enabled() && recorder.last().is_none());
+    recorder.set_enabled(true);
+    for level in 0..5u8 {
+      let frame = [RGBA8 { r: level, g: 0, b: 0, a: 0 }; 2];
+      // recording never waits, so this comes straight back
+      embassy_futures::block_on(recorder.write(&frame));
+    }
+    assert!(recorder.is_enabled());
+    assert_eq!(recorder.written(), 5);
+    assert_eq!(recorder.frames().map(|frame| frame[0].r).collect::<heapless::Vec<_, 3>>(), [2, 3, 4]);
+    assert_eq!(recorder.last().unwrap()[1].r, 4);
+  }
+
+  #[test]
+  fn analog_duty_averages_the_strip() {
+    let lit = RGBA8 { r: 255, g: 128, b: 0, a: 255 };
+    assert_eq!(analog_duty(&[lit], 1_000), [1_000, 501, 0, 1_000]);
+    assert_eq!(analog_duty(&[lit, RGBA8::default()], 1_000), [500, 250, 0, 500]);
+    assert_eq!(analog_duty(&[], 1_000), [0; 4]);
+  }
+}
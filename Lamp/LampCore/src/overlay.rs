@@ -0,0 +1,314 @@
+
+//! Short lived overlay that shows what the knob is about to change whenever the
+//! manager switches state: a brightness bar, a strip of neighbouring hues, or a
+//! sample of the selected effect. Fades back to the normal effect on its own.
+//!
+//! While the button is held past a long press it also counts down toward the
+//! factory reset, with the strip going dark one pixel at a time.
+
+use embassy_time::{Duration, Instant};
+use portable_atomic::{AtomicBool, AtomicU8, Ordering};
+use rand::Rng;
+
+use crate::{
+  color::{lerp8, LampColor, COLOR_MAX, RGBA8},
+  effects::{Effect, Effects},
+  gesture::{LONG_PRESS_IN_MILISECONDS, VERY_LONG_PRESS_IN_MILISECONDS},
+  manager::ManagerStates,
+  persist::Settings
+};
+
+pub const OVERLAY_FADE_IN: Duration = Duration::from_millis(100);
+pub const OVERLAY_HOLD: Duration = Duration::from_millis(1_200);
+pub const OVERLAY_FADE_OUT: Duration = Duration::from_millis(500);
+// the overlay is shown at least this bright, so it still reads on a dimmed lamp
+pub const OVERLAY_MIN_BRIGHTNESS: u8 = 96;
+// from the long press to the very long press
+pub const COUNTDOWN: Duration = Duration::from_millis(VERY_LONG_PRESS_IN_MILISECONDS - LONG_PRESS_IN_MILISECONDS);
+const COUNTDOWN_COLOR: RGBA8 = RGBA8 { r: 255, g: 0, b: 0, a: 0 };
+
+// written by the manager, read by the renderer; the serial lets the same state be shown twice
+#[derive(Default, Debug)]
+pub struct ModeIndication {
+  serial: AtomicU8,
+  state: AtomicU8,
+}
+
+impl ModeIndication {
+  pub const fn new() -> Self {
+    Self { serial: AtomicU8::new(0), state: AtomicU8::new(0) }
+  }
+
+  pub fn show(&self, state: ManagerStates) {
+    self.state.store(state as u8, Ordering::Relaxed);
+    self.serial.fetch_add(1, Ordering::Release);
+  }
+
+  fn latest(&self) -> (u8, ManagerStates) {
+    let serial = self.serial.load(Ordering::Acquire);
+    let state = match self.state.load(Ordering::Relaxed) {
+      s if s == ManagerStates::Color as u8 => ManagerStates::Color,
+      s if s == ManagerStates::Value as u8 => ManagerStates::Value,
+      _ => ManagerStates::Brightness
+    };
+    (serial, state)
+  }
+}
+
+pub static MODE_INDICATION: ModeIndication = ModeIndication::new();
+
+// started by the manager on a long press, cancelled as soon as the button is let go
+#[derive(Default, Debug)]
+pub struct ResetCountdown {
+  serial: AtomicU8,
+  running: AtomicBool,
+}
+
+impl ResetCountdown {
+  pub const fn new() -> Self {
+    Self { serial: AtomicU8::new(0), running: AtomicBool::new(false) }
+  }
+
+  pub fn start(&self) {
+    self.running.store(true, Ordering::Relaxed);
+    self.serial.fetch_add(1, Ordering::Release);
+  }
+
+  pub fn cancel(&self) {
+    self.running.store(false, Ordering::Relaxed);
+  }
+
+  fn latest(&self) -> (u8, bool) {
+    let serial = self.serial.load(Ordering::Acquire);
+    (serial, self.running.load(Ordering::Relaxed))
+  }
+}
+
+pub static RESET_COUNTDOWN: ResetCountdown = ResetCountdown::new();
+
+pub struct Overlay<const N: usize> {
+  serial: u8,
+  shown: Option<(Instant, ManagerStates)>,
+  countdown_serial: u8,
+  countdown: Option<Instant>,
+  // the selected effect and the value it was started for, while the value overlay is up
+  sample: Option<(u8, Effects<N>)>,
+  buffer: [RGBA8; N],
+}
+
+impl<const N: usize> Overlay<N> {
+  pub fn new() -> Self {
+    // anything shown before the renderer existed is stale
+    let (serial, _) = MODE_INDICATION.latest();
+    let (countdown_serial, _) = RESET_COUNTDOWN.latest();
+    Self { serial, shown: None, countdown_serial, countdown: None, sample: None, buffer: [RGBA8::default(); N] }
+  }
+
+  // lit pixels for what's left of the countdown, if one is running
+  fn apply_countdown(&mut self, now: Instant, data: &mut [RGBA8; N]) -> bool {
+    let (serial, running) = RESET_COUNTDOWN.latest();
+    if serial != self.countdown_serial {
+      self.countdown_serial = serial;
+      self.countdown = Some(now);
+    }
+    let Some(since) = self.countdown.filter(|_| running) else {
+      self.countdown = None;
+      return false;
+    };
+    let elapsed = now.saturating_duration_since(since).as_millis();
+    let total = COUNTDOWN.as_millis();
+    if elapsed >= total {
+      self.countdown = None;
+      return false;
+    }
+    let remaining = ((total - elapsed) * (N * 255) as u64 / total) as usize;
+    for (i, led) in data.iter_mut().enumerate() {
+      let fill = (remaining.saturating_sub(i * 255)).min(255) as u8;
+      led.fade_from(&COUNTDOWN_COLOR, fill);
+    }
+    true
+  }
+
+  // 0 when hidden, 255 while fully shown
+  fn strength(&mut self, now: Instant) -> u8 {
+    let Some((since, _)) = self.shown else {
+      return 0;
+    };
+    let elapsed = now.saturating_duration_since(since).as_millis();
+    let fade_in = OVERLAY_FADE_IN.as_millis();
+    let fade_out = OVERLAY_FADE_OUT.as_millis();
+    let hold_end = fade_in + OVERLAY_HOLD.as_millis();
+    if elapsed < fade_in {
+      (elapsed * 255 / fade_in) as u8
+    } else if elapsed < hold_end {
+      255
+    } else if elapsed < hold_end + fade_out {
+      (255 - (elapsed - hold_end) * 255 / fade_out) as u8
+    } else {
+      self.shown = None;
+      0
+    }
+  }
+
+  fn draw(&mut self, now: Instant, state: ManagerStates, settings: &Settings, color: &RGBA8, rng: &mut impl Rng) {
+    if state != ManagerStates::Value {
+      self.sample = None;
+    }
+    match state {
+      // lit up to the brightness, the last pixel partially
+      ManagerStates::Brightness => {
+        let level = settings.brightness as usize * N;
+        for (i, led) in self.buffer.iter_mut().enumerate() {
+          let fill = (level.saturating_sub(i * 255)).min(255) as u8;
+          led.fade_from(color, fill);
+        }
+      }
+      // the selected hue in the middle, its neighbours on the wheel around it
+      ManagerStates::Color => {
+        let span = COLOR_MAX as i16 + 1;
+        for (i, led) in self.buffer.iter_mut().enumerate() {
+          let offset = i as i16 - (N / 2) as i16;
+          led.from_u16((settings.color as i16 + offset).rem_euclid(span) as u8);
+        }
+      }
+      // the selected effect itself, restarted whenever the knob picks another
+      ManagerStates::Value => {
+        let sample = match &mut self.sample {
+          Some((value, sample)) if *value == settings.value => sample,
+          sample => &mut sample.insert((settings.value, Effects::new(settings.value.into(), now, rng))).1,
+        };
+        sample.render(now, color, &mut self.buffer, rng);
+      }
+    }
+  }
+
+  // blends the overlay into data and returns the brightness to post process it with
+  pub fn apply(
+    &mut self,
+    now: Instant,
+    settings: &Settings,
+    color: &RGBA8,
+    brightness: u8,
+    data: &mut [RGBA8; N],
+    rng: &mut impl Rng
+  ) -> u8 {
+    if self.apply_countdown(now, data) {
+      return brightness.max(OVERLAY_MIN_BRIGHTNESS);
+    }
+    let (serial, state) = MODE_INDICATION.latest();
+    if serial != self.serial {
+      self.serial = serial;
+      self.shown = Some((now, state));
+    }
+    let strength = self.strength(now);
+    let Some((_, state)) = self.shown else {
+      self.sample = None;
+      return brightness;
+    };
+    self.draw(now, state, settings, color, rng);
+    for (current, overlay) in data.iter_mut().zip(self.buffer.iter()) {
+      // lerp_from goes from its argument toward self, so blend with the roles swapped
+      let mut blended = *overlay;
+      blended.lerp_from(current, strength);
+      *current = blended;
+    }
+    lerp8(brightness, brightness.max(OVERLAY_MIN_BRIGHTNESS), strength)
+  }
+}
+
+impl<const N: usize> Default for Overlay<N> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use crate::rng::LampRng;
+
+  const SETTINGS: Settings = Settings { brightness: 255, color: 0, value: 0 };
+
+  // these share the global indication statics, so they can't run in parallel
+  #[test]
+  fn overlays() {
+    shows_then_fades_back();
+    counts_down_until_cancelled();
+    samples_the_selected_effect();
+  }
+
+  fn shows_then_fades_back() {
+    let start = Instant::from_millis(0);
+    let color = RGBA8 { r: 255, g: 0, b: 0, a: 0 };
+    let mut rng = LampRng::new(1);
+    let mut overlay = Overlay::<5>::new();
+    let mut data = [RGBA8::default(); 5];
+    assert_eq!(overlay.apply(start, &SETTINGS, &color, 10, &mut data, &mut rng), 10);
+
+    MODE_INDICATION.show(ManagerStates::Color);
+    let shown = start + OVERLAY_FADE_IN + Duration::from_millis(10);
+    overlay.apply(start, &SETTINGS, &color, 10, &mut data, &mut rng);
+    assert_eq!(overlay.apply(shown, &SETTINGS, &color, 10, &mut data, &mut rng), OVERLAY_MIN_BRIGHTNESS);
+    // the selected color (warm white by default) sits in the middle
+    assert_eq!(data[2], RGBA8 { r: 0, g: 0, b: 0, a: 255 });
+
+    let gone = shown + OVERLAY_HOLD + OVERLAY_FADE_OUT;
+    let mut data = [color; 5];
+    assert_eq!(overlay.apply(gone, &SETTINGS, &color, 10, &mut data, &mut rng), 10);
+    assert_eq!(data, [color; 5]);
+  }
+
+  fn counts_down_until_cancelled() {
+    let start = Instant::from_millis(0);
+    let color = RGBA8 { r: 0, g: 0, b: 0, a: 255 };
+    let mut rng = LampRng::new(1);
+    let mut overlay = Overlay::<4>::new();
+    let mut data = [color; 4];
+    RESET_COUNTDOWN.start();
+    overlay.apply(start, &SETTINGS, &color, 10, &mut data, &mut rng);
+    assert!(data.iter().all(|led| *led == COUNTDOWN_COLOR));
+
+    // half way, half the strip is dark
+    let mut data = [color; 4];
+    overlay.apply(start + COUNTDOWN / 2, &SETTINGS, &color, 10, &mut data, &mut rng);
+    assert_eq!(data[1], COUNTDOWN_COLOR);
+    assert_eq!(data[2], RGBA8::default());
+
+    RESET_COUNTDOWN.cancel();
+    let mut data = [color; 4];
+    assert_eq!(overlay.apply(start + COUNTDOWN / 2, &SETTINGS, &color, 10, &mut data, &mut rng), 10);
+    assert_eq!(data, [color; 4]);
+  }
+
+  // frames of the effect on its own, started and drawn at the same times as the overlay's sample
+  fn reference(value: u8, times: &[Instant], color: &RGBA8, rng: &mut LampRng) -> [RGBA8; 5] {
+    let mut effect = Effects::<5>::new(value.into(), times[0], rng);
+    let mut data = [RGBA8::default(); 5];
+    for now in times {
+      effect.render(*now, color, &mut data, rng);
+    }
+    data
+  }
+
+  fn samples_the_selected_effect() {
+    let start = Instant::from_millis(0);
+    let shown = start + OVERLAY_FADE_IN + Duration::from_millis(10);
+    let color = RGBA8 { r: 255, g: 120, b: 0, a: 0 };
+    let candle = Settings { value: 4, ..SETTINGS };
+    let mut rng = LampRng::new(1);
+    let mut overlay = Overlay::<5>::new();
+    let mut data = [RGBA8::default(); 5];
+    MODE_INDICATION.show(ManagerStates::Value);
+    overlay.apply(start, &candle, &color, 10, &mut data, &mut rng);
+    overlay.apply(shown, &candle, &color, 10, &mut data, &mut rng);
+    assert_eq!(data, reference(4, &[start, shown], &color, &mut LampRng::new(1)));
+
+    // turning the knob restarts the sample as the newly selected effect
+    let later = shown + Duration::from_millis(10);
+    let sparkle = Settings { value: 6, ..SETTINGS };
+    let mut expected_rng = rng.clone();
+    overlay.apply(later, &sparkle, &color, 10, &mut data, &mut rng);
+    assert_eq!(data, reference(6, &[later], &color, &mut expected_rng));
+  }
+}
@@ -0,0 +1,110 @@
+
+//! The on-flash format of the lamp settings.
+//!
+//! Firmware before versioning stored three raw bytes (brightness, color, value)
+//! under `LEGACY_STORE_KEY`. Everything since is stored under `STORE_KEY` as a
+//! schema version byte followed by the postcard encoding of that version's
+//! struct. Adding a field means adding a `SettingsV{n}`, pointing `Settings` at
+//! it and teaching `decode` to migrate the older versions forward.
+
+use serde::{Deserialize, Serialize};
+
+pub const LEGACY_STORE_KEY: u8 = 0;
+pub const STORE_KEY: u8 = 1;
+
+pub const SETTINGS_VERSION: u8 = 1;
+pub type Settings = SettingsV1;
+
+// big enough for any version, with room to grow
+pub const SETTINGS_BUFFER_SIZE: usize = 32;
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SettingsV1 {
+  pub brightness: u8,
+  pub color: u8,
+  // index into the effect registry
+  pub value: u8
+}
+
+impl Default for SettingsV1 {
+  fn default() -> Self {
+    Self {
+      brightness: 255,
+      color: 0,
+      value: 0
+    }
+  }
+}
+
+#[derive(PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DecodeError {
+  Empty,
+  // written by newer firmware than this one
+  UnknownVersion(u8),
+  Corrupt
+}
+
+pub fn encode<'a>(settings: &Settings, buffer: &'a mut [u8]) -> Result<&'a [u8], postcard::Error> {
+  let (version, body) = buffer.split_first_mut().ok_or(postcard::Error::SerializeBufferFull)?;
+  *version = SETTINGS_VERSION;
+  let len = postcard::to_slice(settings, body)?.len();
+  Ok(&buffer[..len + 1])
+}
+
+pub fn decode(data: &[u8]) -> Result<Settings, DecodeError> {
+  let (version, body) = data.split_first().ok_or(DecodeError::Empty)?;
+  match *version {
+    1 => postcard::from_bytes::<SettingsV1>(body).map_err(|_| DecodeError::Corrupt),
+    other => Err(DecodeError::UnknownVersion(other))
+  }
+}
+
+// the three raw bytes written under LEGACY_STORE_KEY before the store was versioned
+pub fn migrate_legacy(data: &[u8]) -> Result<Settings, DecodeError> {
+  match data {
+    [] => Err(DecodeError::Empty),
+    [brightness, color, value] => Ok(SettingsV1 {
+      brightness: *brightness,
+      color: *color,
+      value: *value
+    }),
+    _ => Err(DecodeError::Corrupt)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn round_trips_the_current_version() {
+    let settings = Settings { brightness: 12, color: 30, value: 4 };
+    let mut buffer = [0; SETTINGS_BUFFER_SIZE];
+    let encoded = encode(&settings, &mut buffer).unwrap();
+    assert_eq!(decode(encoded), Ok(settings));
+  }
+
+  #[test]
+  fn decodes_blobs_from_older_firmware() {
+    // unversioned store, as written by the first shipped lamps
+    assert_eq!(
+      migrate_legacy(&[192, 7, 2]),
+      Ok(SettingsV1 { brightness: 192, color: 7, value: 2 })
+    );
+    // version 1, as written by the first versioned firmware
+    assert_eq!(
+      decode(&[1, 192, 7, 2]),
+      Ok(SettingsV1 { brightness: 192, color: 7, value: 2 })
+    );
+  }
+
+  #[test]
+  fn rejects_what_it_cannot_read() {
+    assert_eq!(decode(&[]), Err(DecodeError::Empty));
+    assert_eq!(decode(&[9, 1, 2, 3]), Err(DecodeError::UnknownVersion(9)));
+    assert_eq!(decode(&[1, 192]), Err(DecodeError::Corrupt));
+    assert_eq!(migrate_legacy(&[1, 2]), Err(DecodeError::Corrupt));
+  }
+}
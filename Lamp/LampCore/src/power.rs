@@ -0,0 +1,244 @@
+
+use embassy_time::{Duration, Instant};
+use portable_atomic::{AtomicU32, Ordering};
+
+use crate::color::{scale8, RGBA8};
+
+// how long the limiter takes to give brightness back from dark to full once the frames
+// fit the budget again; the 4 steps per 10 ms tick it used to take
+pub const POWER_RECOVER_TIME: Duration = Duration::from_millis(640);
+
+// current model of one led: draw of each channel at full duty, plus what the driver ic
+// pulls while dark. defaults are the sk6812 rgbw datasheet figures
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PowerModel {
+  pub red_ma: u16,
+  pub green_ma: u16,
+  pub blue_ma: u16,
+  pub white_ma: u16,
+  pub idle_ma: u16,
+}
+
+pub const SK6812_RGBW: PowerModel = PowerModel {
+  red_ma: 20,
+  green_ma: 20,
+  blue_ma: 20,
+  white_ma: 20,
+  idle_ma: 1,
+};
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PowerBudget {
+  pub model: PowerModel,
+  // ceiling for the whole strip, idle draw included
+  pub limit_ma: u32,
+}
+
+// the desk lamp has to run from any usb port, and without negotiating for more a port
+// only promises 500 mA; the rp2040, flash and indicator leds take the rest
+pub const DESK_BUDGET: PowerBudget = PowerBudget { model: SK6812_RGBW, limit_ma: 350 };
+
+// from the installation notes: a section powers at most 71 fish, a module each, on
+// 213 W of the 24 V rail (the 8.875 A it is rated for)
+pub const SECTION_POWER_MW: u32 = 213_000;
+pub const SECTION_MODULE_COUNT: u32 = 71;
+// the leds sit on 5 V, behind each module's regulator
+pub const LED_RAIL_MV: u32 = 5_000;
+
+// one module's share of its section, as current on the led rail: 3 W, so 600 mA. Regulator
+// losses are left out, so this errs high. Five leds at full white only take 405 mA, so
+// on the shipped board the limiter is a guard for longer strips rather than a dimmer
+pub const MODULE_BUDGET: PowerBudget = PowerBudget {
+  model: SK6812_RGBW,
+  limit_ma: SECTION_POWER_MW / SECTION_MODULE_COUNT * 1_000 / LED_RAIL_MV,
+};
+
+// last estimate of the strip draw, after limiting; for the console and the simulator
+pub static ESTIMATED_CURRENT_MA: AtomicU32 = AtomicU32::new(0);
+
+pub fn estimated_current_ma() -> u32 {
+  ESTIMATED_CURRENT_MA.load(Ordering::Relaxed)
+}
+
+fn channel_sums(frame: &[RGBA8]) -> [u32; 4] {
+  let mut sums = [0u32; 4];
+  for led in frame.iter() {
+    sums[0] += led.r as u32;
+    sums[1] += led.g as u32;
+    sums[2] += led.b as u32;
+    sums[3] += led.a as u32;
+  }
+  sums
+}
+
+impl PowerModel {
+  pub fn idle_current_ma(&self, led_count: usize) -> u32 {
+    self.idle_ma as u32 * led_count as u32
+  }
+
+  // draw of the channels only; the idle draw can't be scaled away
+  pub fn channel_current_ma(&self, frame: &[RGBA8]) -> u32 {
+    let [r, g, b, w] = channel_sums(frame);
+    (r * self.red_ma as u32 + g * self.green_ma as u32 + b * self.blue_ma as u32 + w * self.white_ma as u32) / 255
+  }
+
+  pub fn current_ma(&self, frame: &[RGBA8]) -> u32 {
+    self.idle_current_ma(frame.len()) + self.channel_current_ma(frame)
+  }
+}
+
+// scales frames down to the budget. cuts right away when a frame is over, so we never
+// draw more than the limit, and eases back up so the lamp doesn't pump
+pub struct PowerLimiter {
+  budget: PowerBudget,
+  scale: u8,
+  // when the scale was last cut, and to what; the recovery counts up from there.
+  // None while it is at full
+  cut: Option<(Instant, u8)>,
+}
+
+impl PowerLimiter {
+  pub const fn new(budget: PowerBudget) -> Self {
+    Self { budget, scale: 255, cut: None }
+  }
+
+  pub fn scale(&self) -> u8 {
+    self.scale
+  }
+
+  // largest scale at which the frame still fits the budget
+  fn allowed_scale(&self, frame: &[RGBA8]) -> u8 {
+    let channel_ma = self.budget.model.channel_current_ma(frame);
+    let available_ma = self.budget.limit_ma.saturating_sub(self.budget.model.idle_current_ma(frame.len()));
+    if channel_ma <= available_ma {
+      return 255;
+    }
+    // scale8 maps 255 to x * 256 / 256, so anything below 255 works out as x * (scale + 1) / 256
+    ((available_ma * 256 / channel_ma).max(1) - 1) as u8
+  }
+
+  // returns the estimated draw of the frame as it will be written
+  pub fn limit(&mut self, frame: &mut [RGBA8], now: Instant) -> u32 {
+    let allowed = self.allowed_scale(frame);
+    // by elapsed time rather than per frame, so the tick rate doesn't change how fast it comes back
+    let recovered = self.cut.map_or(255, |(since, from)| {
+      let elapsed = now.saturating_duration_since(since).as_millis();
+      (from as u64 + elapsed * 255 / POWER_RECOVER_TIME.as_millis()).min(255) as u8
+    });
+    self.scale = recovered.min(allowed);
+    if self.scale == 255 {
+      self.cut = None;
+    } else if self.scale < recovered {
+      self.cut = Some((now, self.scale));
+    }
+    if self.scale < 255 {
+      for led in frame.iter_mut() {
+        led.r = scale8(led.r, self.scale);
+        led.g = scale8(led.g, self.scale);
+        led.b = scale8(led.b, self.scale);
+        led.a = scale8(led.a, self.scale);
+      }
+    }
+    let estimated_ma = self.budget.model.current_ma(frame);
+    ESTIMATED_CURRENT_MA.store(estimated_ma, Ordering::Relaxed);
+    estimated_ma
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  extern crate std;
+
+  use super::*;
+
+  const WHITE: RGBA8 = RGBA8 { r: 255, g: 255, b: 255, a: 255 };
+
+  #[test]
+  fn estimates_full_white() {
+    let frame = [WHITE; 5];
+    assert_eq!(SK6812_RGBW.current_ma(&frame), 5 * (4 * 20 + 1));
+    assert_eq!(SK6812_RGBW.current_ma(&[RGBA8::default(); 5]), 5);
+  }
+
+  #[test]
+  fn stays_under_the_limit_and_recovers() {
+    let start = Instant::from_millis(0);
+    let budget = PowerBudget { model: SK6812_RGBW, limit_ma: 200 };
+    let mut limiter = PowerLimiter::new(budget);
+    let mut frame = [WHITE; 5];
+    assert!(limiter.limit(&mut frame, start) <= budget.limit_ma);
+    assert!(limiter.scale() < 255);
+
+    // a dark frame fits, but the scale only comes back over POWER_RECOVER_TIME
+    let scale = limiter.scale();
+    let mut frame = [RGBA8::default(); 5];
+    limiter.limit(&mut frame, start + POWER_RECOVER_TIME / 4);
+    assert_eq!(limiter.scale(), scale + 63);
+
+    let mut frame = [RGBA8 { r: 255, g: 0, b: 0, a: 0 }; 1];
+    limiter.limit(&mut frame, start + POWER_RECOVER_TIME);
+    assert_eq!(limiter.scale(), 255);
+  }
+
+  // the scale after each of `frames`, rendered every `tick`
+  fn recovery(tick: Duration, frames: u64) -> std::vec::Vec<(u64, u8)> {
+    let mut limiter = PowerLimiter::new(PowerBudget { model: SK6812_RGBW, limit_ma: 200 });
+    (0..frames)
+      .map(|frame| {
+        let now = Instant::from_millis(0) + tick * frame as u32;
+        // over budget up to 40 ms, a frame both tick rates render, dark after that
+        let mut data = if now.as_millis() <= 40 { [WHITE; 5] } else { [RGBA8::default(); 5] };
+        limiter.limit(&mut data, now);
+        (now.as_millis(), limiter.scale())
+      })
+      .collect()
+  }
+
+  #[test]
+  fn recovers_at_the_same_pace_at_any_tick_rate() {
+    let fast = recovery(Duration::from_millis(5), 160);
+    let slow = recovery(Duration::from_millis(10), 80);
+    // every frame the slow ticker rendered, the fast one rendered too and got the same scale
+    for step in &slow {
+      assert!(fast.contains(step), "{:?} missing at 5 ms", step);
+    }
+    assert_eq!(slow.last().unwrap().1, 255);
+    assert!(slow.iter().any(|(_, scale)| *scale < 255));
+  }
+
+  #[test]
+  fn desk_budget_engages() {
+    let mut limiter = PowerLimiter::new(DESK_BUDGET);
+    let mut frame = [WHITE; 5];
+    assert!(limiter.limit(&mut frame, Instant::from_millis(0)) <= DESK_BUDGET.limit_ma);
+    assert!(limiter.scale() < 255);
+    assert!(frame[0].r < 255);
+  }
+
+  #[test]
+  fn module_budget_is_its_share_of_the_section() {
+    assert_eq!(MODULE_BUDGET.limit_ma, 600);
+    // all 71 modules at their limit stay inside the section's 213 W
+    const { assert!(SECTION_MODULE_COUNT * MODULE_BUDGET.limit_ma * LED_RAIL_MV / 1_000 <= SECTION_POWER_MW) };
+
+    // the shipped five leds fit at full white
+    let mut limiter = PowerLimiter::new(MODULE_BUDGET);
+    let mut frame = [WHITE; 5];
+    assert_eq!(limiter.limit(&mut frame, Instant::from_millis(0)), 405);
+    assert_eq!(limiter.scale(), 255);
+    // eight would not
+    let mut frame = [WHITE; 8];
+    assert!(limiter.limit(&mut frame, Instant::from_millis(0)) <= MODULE_BUDGET.limit_ma);
+    assert!(limiter.scale() < 255);
+  }
+
+  #[test]
+  fn untouched_when_under_budget() {
+    let mut limiter = PowerLimiter::new(PowerBudget { model: SK6812_RGBW, limit_ma: 1000 });
+    let mut frame = [WHITE; 5];
+    assert_eq!(limiter.limit(&mut frame, Instant::from_millis(0)), 405);
+    assert_eq!(frame, [WHITE; 5]);
+  }
+}
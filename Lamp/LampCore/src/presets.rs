@@ -0,0 +1,239 @@
+
+//! Named scenes the user can save and recall. Each slot is its own key in the
+//! same flash map as the store, encoded like the store: a version byte followed
+//! by postcard.
+
+use core::fmt::{self, Write};
+
+use heapless::String;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  persist::{DecodeError, Settings},
+  store::AtomicStore
+};
+
+pub const PRESET_COUNT: u8 = 4;
+// slot n lives under PRESET_KEY_BASE + n, clear of the store keys
+pub const PRESET_KEY_BASE: u8 = 16;
+pub const PRESET_NAME_LENGTH: usize = 12;
+
+pub const PRESET_VERSION: u8 = 1;
+pub const PRESET_BUFFER_SIZE: usize = 32;
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct Preset {
+  pub name: String<PRESET_NAME_LENGTH>,
+  pub settings: Settings
+}
+
+pub fn preset_key(slot: u8) -> u8 {
+  PRESET_KEY_BASE + slot
+}
+
+// names longer than PRESET_NAME_LENGTH are cut short
+pub fn preset_name(name: &str) -> String<PRESET_NAME_LENGTH> {
+  let mut owned = String::new();
+  for c in name.chars() {
+    if owned.push(c).is_err() {
+      break;
+    }
+  }
+  owned
+}
+
+pub fn encode<'a>(preset: &Preset, buffer: &'a mut [u8]) -> Result<&'a [u8], postcard::Error> {
+  let (version, body) = buffer.split_first_mut().ok_or(postcard::Error::SerializeBufferFull)?;
+  *version = PRESET_VERSION;
+  let len = postcard::to_slice(preset, body)?.len();
+  Ok(&buffer[..len + 1])
+}
+
+pub fn decode(data: &[u8]) -> Result<Preset, DecodeError> {
+  let (version, body) = data.split_first().ok_or(DecodeError::Empty)?;
+  match *version {
+    1 => postcard::from_bytes::<Preset>(body).map_err(|_| DecodeError::Corrupt),
+    other => Err(DecodeError::UnknownVersion(other))
+  }
+}
+
+// which slots hold a preset, a bit each; all the manager needs to know about them
+#[derive(PartialEq, Eq, Clone, Copy, Default, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SavedSlots(u8);
+
+impl SavedSlots {
+  pub const fn new() -> Self {
+    Self(0)
+  }
+
+  pub fn contains(&self, slot: u8) -> bool {
+    slot < PRESET_COUNT && self.0 & (1 << slot) != 0
+  }
+
+  // slots past PRESET_COUNT are ignored
+  pub fn insert(&mut self, slot: u8) {
+    if slot < PRESET_COUNT {
+      self.0 |= 1 << slot;
+    }
+  }
+
+  // where a save without a slot goes; None once every slot holds a preset
+  pub fn first_free(&self) -> Option<u8> {
+    (0..PRESET_COUNT).find(|slot| !self.contains(*slot))
+  }
+
+  // the first saved slot after `after`, wrapping around; None if nothing is saved
+  pub fn next_after(&self, after: Option<u8>) -> Option<u8> {
+    let start = after.map_or(0, |slot| slot + 1);
+    (0..PRESET_COUNT)
+      .map(|offset| (start + offset) % PRESET_COUNT)
+      .find(|slot| self.contains(*slot))
+  }
+}
+
+// RAM copy of every slot, so recalling one doesn't have to touch flash
+#[derive(Default, Debug)]
+pub struct PresetSlots {
+  slots: [Option<Preset>; PRESET_COUNT as usize],
+}
+
+impl PresetSlots {
+  pub const fn new() -> Self {
+    Self { slots: [const { None }; PRESET_COUNT as usize] }
+  }
+
+  pub fn get(&self, slot: u8) -> Option<&Preset> {
+    self.slots.get(slot as usize)?.as_ref()
+  }
+
+  pub fn clear(&mut self) {
+    self.slots = [const { None }; PRESET_COUNT as usize];
+  }
+
+  // a preset read back from flash
+  pub fn insert(&mut self, slot: u8, preset: Preset) {
+    if let Some(entry) = self.slots.get_mut(slot as usize) {
+      *entry = Some(preset);
+    }
+  }
+
+  // the preset a save would put in the slot, without putting it there yet. an empty
+  // name keeps the slot's name, or becomes "preset <n>" for a new one
+  pub fn build(&self, slot: u8, name: &str, settings: Settings) -> Option<Preset> {
+    let entry = self.slots.get(slot as usize)?;
+    let name = match (name, entry) {
+      ("", Some(old)) => old.name.clone(),
+      ("", None) => {
+        let mut name = String::new();
+        let _ = write!(name, "preset {}", slot);
+        name
+      }
+      (name, _) => preset_name(name)
+    };
+    Some(Preset { name, settings })
+  }
+
+  pub fn set(&mut self, slot: u8, name: &str, settings: Settings) -> Option<&Preset> {
+    let preset = self.build(slot, name, settings)?;
+    self.insert(slot, preset);
+    self.get(slot)
+  }
+
+  // saves the store as it is now; returns the preset to write to flash
+  pub fn save_current(&mut self, slot: u8, name: &str, store: &AtomicStore) -> Option<&Preset> {
+    self.set(slot, name, store.settings())
+  }
+
+  // the renderer eases into the new settings like after any other change
+  pub fn recall(&self, slot: u8, store: &AtomicStore) -> bool {
+    let Some(preset) = self.get(slot) else {
+      return false;
+    };
+    store.apply_settings(&preset.settings);
+    true
+  }
+
+  pub fn saved(&self) -> SavedSlots {
+    let mut saved = SavedSlots::new();
+    for (slot, preset) in self.slots.iter().enumerate() {
+      if preset.is_some() {
+        saved.insert(slot as u8);
+      }
+    }
+    saved
+  }
+
+  pub fn next_saved(&self, after: Option<u8>) -> Option<u8> {
+    self.saved().next_after(after)
+  }
+
+  pub fn write_list(&self, reply: &mut impl Write) -> fmt::Result {
+    for (slot, preset) in self.slots.iter().enumerate() {
+      if slot > 0 {
+        reply.write_char(' ')?;
+      }
+      match preset {
+        Some(preset) => write!(reply, "{}={}", slot, preset.name)?,
+        None => write!(reply, "{}=-", slot)?,
+      }
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn round_trips_a_preset() {
+    let mut slots = PresetSlots::new();
+    let preset = slots.set(2, "reading", Settings { brightness: 80, color: 5, value: 3 }).unwrap().clone();
+    let mut buffer = [0; PRESET_BUFFER_SIZE];
+    let encoded = encode(&preset, &mut buffer).unwrap();
+    assert_eq!(decode(encoded), Ok(preset));
+    assert_eq!(decode(&[7]), Err(DecodeError::UnknownVersion(7)));
+  }
+
+  #[test]
+  fn names_and_cycles_slots() {
+    let mut slots = PresetSlots::new();
+    assert_eq!(slots.next_saved(None), None);
+    assert!(slots.set(PRESET_COUNT, "nope", Settings::default()).is_none());
+    slots.set(1, "", Settings::default());
+    slots.set(3, "a very long preset name", Settings::default());
+    assert_eq!(slots.get(1).unwrap().name.as_str(), "preset 1");
+    assert_eq!(slots.get(3).unwrap().name.as_str(), "a very long ");
+    slots.set(3, "", Settings { brightness: 1, color: 2, value: 3 });
+    assert_eq!(slots.get(3).unwrap().name.as_str(), "a very long ");
+    assert_eq!(slots.next_saved(None), Some(1));
+    assert_eq!(slots.next_saved(Some(1)), Some(3));
+    assert_eq!(slots.next_saved(Some(3)), Some(1));
+    assert_eq!(slots.saved().first_free(), Some(0));
+
+    // building leaves the slot alone until it is inserted
+    let built = slots.build(0, "", Settings::default()).unwrap();
+    assert_eq!(built.name.as_str(), "preset 0");
+    assert_eq!(slots.get(0), None);
+    slots.insert(0, built);
+    slots.set(2, "", Settings::default());
+    assert_eq!(slots.saved().first_free(), None);
+
+    let mut list: String<64> = String::new();
+    slots.write_list(&mut list).unwrap();
+    assert_eq!(list.as_str(), "0=preset 0 1=preset 1 2=preset 2 3=a very long ");
+  }
+
+  #[test]
+  fn recalls_into_the_store() {
+    let store = AtomicStore::new();
+    let mut slots = PresetSlots::new();
+    store.set_color(9);
+    slots.save_current(0, "warm", &store);
+    store.reset_state();
+    assert!(!slots.recall(1, &store));
+    assert!(slots.recall(0, &store));
+    assert_eq!(store.settings().color, 9);
+  }
+}
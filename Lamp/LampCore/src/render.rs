@@ -0,0 +1,193 @@
+
+use core::mem;
+
+use embassy_time::{Duration, Instant};
+use portable_atomic::{AtomicU32, Ordering};
+use rand::Rng;
+
+use crate::{
+  color::{LampColor, RGBA8},
+  effects::{Effect, Effects},
+  gamma::GAMMA,
+  overlay::Overlay,
+  power::{PowerBudget, PowerLimiter},
+  store::{get_store, step_toward_store, Store, StoreTransitions, STORE}
+};
+
+pub const TICK_RATE_IN_MS: u64 = 10;
+
+// how long the last next_frame took, as measured by whoever drives the renderer; for the console
+pub static FRAME_TIME_US: AtomicU32 = AtomicU32::new(0);
+
+pub fn record_frame_time(elapsed: Duration) {
+  FRAME_TIME_US.store(elapsed.as_micros().min(u32::MAX as u64) as u32, Ordering::Relaxed);
+}
+
+pub fn frame_time_us() -> u32 {
+  FRAME_TIME_US.load(Ordering::Relaxed)
+}
+
+// everything lights_task needs to turn the store into frames of N pixels; one frame per tick
+pub struct Renderer<const N: usize> {
+  data_buffer: [RGBA8; N],
+  // on switch we fade into the new mode from the old one, so it isn't jarring
+  crossfade: Crossfade<N>,
+  // used so we don't post_process data
+  frame_buffer: [RGBA8; N],
+  local_store: Store,
+  target_store: Store,
+  transitions: StoreTransitions,
+  effect: Effects<N>,
+  limiter: PowerLimiter,
+  overlay: Overlay<N>,
+}
+
+impl<const N: usize> Renderer<N> {
+  pub fn new(now: Instant, rng: &mut impl Rng, budget: PowerBudget) -> Self {
+    let mut local_store = get_store();
+    local_store.brightness = 0;
+    let effect = Effects::new(local_store.value.effect, now, rng);
+    let transitions = StoreTransitions::new(&local_store);
+    Self {
+      data_buffer: [RGBA8::default(); N],
+      crossfade: Crossfade::new(),
+      frame_buffer: [RGBA8::default(); N],
+      local_store,
+      target_store: get_store(),
+      transitions,
+      effect,
+      limiter: PowerLimiter::new(budget),
+      overlay: Overlay::new(),
+    }
+  }
+
+  // what to write as soon as the lights are powered
+  pub fn off_frame(&mut self) -> &[RGBA8; N] {
+    set_off(&mut self.frame_buffer);
+    &self.frame_buffer
+  }
+
+  pub fn next_frame(&mut self, now: Instant, rng: &mut impl Rng) -> &[RGBA8; N] {
+    // one read of the store per frame, so the overlay shows exactly what is being stepped toward
+    let settings = STORE.settings();
+    self.target_store.update_from(&settings);
+    if self.target_store != self.local_store
+      && step_toward_store(&self.target_store, &mut self.local_store, &mut self.transitions, now)
+    {
+      // effect switches and preset recalls both end up here
+      let previous = mem::replace(&mut self.effect, Effects::new(self.local_store.value.effect, now, rng));
+      self.crossfade.start(previous);
+    }
+    self.effect.render(now, &self.local_store.color, &mut self.data_buffer, rng);
+    self.crossfade.apply(now, self.local_store.value.pct, &self.local_store.color, &mut self.data_buffer, rng);
+    let brightness = self.overlay.apply(
+      now, &settings, &self.local_store.color, self.local_store.brightness, &mut self.data_buffer, rng
+    );
+    // todo: maybe brightness should be an input to the effect
+    post_process(&mut self.frame_buffer, &self.data_buffer, brightness);
+    // after gamma, so the estimate sees the duty the leds are actually driven at
+    self.limiter.limit(&mut self.frame_buffer, now);
+    &self.frame_buffer
+  }
+}
+
+fn set_off<const N: usize>(data: &mut [RGBA8; N]) {
+  for led in data.iter_mut() {
+    led.r = 0;
+    led.g = 0;
+    led.b = 0;
+    led.a = 0;
+  }
+}
+
+// the effect being faded out, still animating underneath the new one until the fade is done
+struct Crossfade<const N: usize> {
+  previous: Option<Effects<N>>,
+  buffer: [RGBA8; N],
+}
+
+impl<const N: usize> Crossfade<N> {
+  fn new() -> Self {
+    Self { previous: None, buffer: [RGBA8::default(); N] }
+  }
+
+  // switching again mid fade drops the oldest effect, and fades out of the one that was coming in
+  fn start(&mut self, previous: Effects<N>) {
+    self.previous = Some(previous);
+  }
+
+  fn apply(&mut self, now: Instant, pct: u8, color: &RGBA8, data: &mut [RGBA8; N], rng: &mut impl Rng) {
+    if pct == 255 {
+      self.previous = None;
+      return;
+    }
+    let Some(previous) = self.previous.as_mut() else {
+      return;
+    };
+    previous.render(now, color, &mut self.buffer, rng);
+    lerp_with_last(pct, data, &self.buffer);
+  }
+}
+
+fn lerp_with_last<const N: usize>(pct: u8, data: &mut [RGBA8; N], last_data: &[RGBA8; N]) {
+  for (current, last) in data.iter_mut().zip(last_data.iter()) {
+    current.lerp_from(last, pct);
+  }
+}
+
+fn post_process<const N: usize>(frame_buffer: &mut [RGBA8; N], data_buffer: &[RGBA8; N], brightness: u8) {
+  for (out_led, led) in frame_buffer.iter_mut().zip(data_buffer.iter()) {
+    out_led.post_process(led, brightness, &GAMMA);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rand::{rngs::SmallRng, SeedableRng};
+
+  use crate::{effects::{EffectKind, EFFECTS}, power::SK6812_RGBW};
+
+  const BUDGET: PowerBudget = PowerBudget { model: SK6812_RGBW, limit_ma: 1000 };
+
+  #[test]
+  fn renders_any_pixel_count() {
+    let mut rng = SmallRng::seed_from_u64(0);
+    let now = Instant::from_millis(0);
+    let mut small = Renderer::<5>::new(now, &mut rng, BUDGET);
+    let mut large = Renderer::<12>::new(now, &mut rng, BUDGET);
+    assert!(small.off_frame().iter().all(|led| *led == RGBA8::default()));
+    assert!(large.off_frame().iter().all(|led| *led == RGBA8::default()));
+    assert_eq!(small.next_frame(now, &mut rng).len(), 5);
+    assert_eq!(large.next_frame(now, &mut rng).len(), 12);
+  }
+
+  #[test]
+  fn crossfade_keeps_the_old_effect_moving() {
+    let start = Instant::from_millis(0);
+    let red = RGBA8 { r: 255, g: 0, b: 0, a: 0 };
+    // breathing changes with time, so a frozen frame would show
+    let breathing = EFFECTS.iter().position(|kind| *kind == EffectKind::Breathing).unwrap() as u8;
+    let mut rng = SmallRng::seed_from_u64(0);
+    let mut reference = Effects::<4>::new(breathing.into(), start, &mut rng);
+    let mut crossfade = Crossfade::new();
+    crossfade.start(Effects::new(breathing.into(), start, &mut rng));
+
+    let mut frames = [[RGBA8::default(); 4]; 2];
+    for (frame, at) in frames.iter_mut().zip([500, 1_500]) {
+      let now = start + Duration::from_millis(at);
+      let mut expected = [RGBA8::default(); 4];
+      reference.render(now, &red, &mut expected, &mut rng);
+      // fully on the old side, the output is exactly the old effect as it is now
+      crossfade.apply(now, 0, &red, frame, &mut rng);
+      assert_eq!(*frame, expected);
+    }
+    assert_ne!(frames[0], frames[1]);
+
+    // once the fade is over the old effect is let go and nothing is blended in
+    let mut data = [red; 4];
+    crossfade.apply(start, 255, &red, &mut data, &mut rng);
+    assert!(crossfade.previous.is_none());
+    assert_eq!(data, [red; 4]);
+  }
+}
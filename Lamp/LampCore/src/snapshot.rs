@@ -0,0 +1,169 @@
+
+//! Every effect rendered for twelve seconds at a fixed seed and frame rate, so a
+//! change to how any of them looks shows up as a diff against the golden files
+//! in `golden/`. `UPDATE_GOLDEN=1 cargo test golden` rewrites them, and
+//! `cargo run --example snapshots --features snapshot` draws them next to the
+//! current output as png. Only built for tests and with the `snapshot` feature.
+//!
+//! File format: a version byte, the led count, then runs of identical frames as
+//! a repeat count followed by the frame's bytes, four per led.
+
+use embassy_time::{Duration, Instant};
+
+use crate::{
+  color::RGBA8,
+  effects::{Effect, EffectId, Effects},
+  persist::DecodeError,
+  render::TICK_RATE_IN_MS,
+  rng::LampRng
+};
+
+pub const SNAPSHOT_VERSION: u8 = 1;
+pub const SNAPSHOT_SEED: u64 = 1;
+pub const SNAPSHOT_LEDS: usize = 5;
+// 12 seconds of ticks: a full cycle of the slowest walker (chill, at most 10.75 s) with
+// room for the walkers that start part way into theirs
+pub const SNAPSHOT_FRAMES: usize = 1_200;
+// every channel lit, so effects that shift color show it
+pub const SNAPSHOT_COLOR: RGBA8 = RGBA8 { r: 255, g: 160, b: 64, a: 32 };
+// enough for frames that never repeat
+pub const SNAPSHOT_BUFFER_SIZE: usize = 2 + SNAPSHOT_FRAMES * (1 + SNAPSHOT_LEDS * 4);
+
+pub type SnapshotFrame = [RGBA8; SNAPSHOT_LEDS];
+
+pub fn render_snapshot(id: EffectId, frames: &mut [SnapshotFrame]) {
+  let mut rng = LampRng::new(SNAPSHOT_SEED);
+  let start = Instant::from_millis(0);
+  let mut effect = Effects::<SNAPSHOT_LEDS>::new(id, start, &mut rng);
+  for (tick, frame) in frames.iter_mut().enumerate() {
+    let now = start + Duration::from_millis(tick as u64 * TICK_RATE_IN_MS);
+    effect.render(now, &SNAPSHOT_COLOR, frame, &mut rng);
+  }
+}
+
+// None if the buffer is too small
+pub fn encode(frames: &[SnapshotFrame], buffer: &mut [u8]) -> Option<usize> {
+  let mut len = 0;
+  let mut push = |byte: u8| {
+    *buffer.get_mut(len)? = byte;
+    len += 1;
+    Some(())
+  };
+  push(SNAPSHOT_VERSION)?;
+  push(SNAPSHOT_LEDS as u8)?;
+  let mut remaining = frames;
+  while let Some(frame) = remaining.first() {
+    let run = remaining.iter().take(u8::MAX as usize).take_while(|other| *other == frame).count();
+    push(run as u8)?;
+    for led in frame.iter() {
+      for byte in [led.r, led.g, led.b, led.a] {
+        push(byte)?;
+      }
+    }
+    remaining = &remaining[run..];
+  }
+  Some(len)
+}
+
+// returns how many frames were filled in
+pub fn decode(data: &[u8], frames: &mut [SnapshotFrame]) -> Result<usize, DecodeError> {
+  let (version, body) = data.split_first().ok_or(DecodeError::Empty)?;
+  if *version != SNAPSHOT_VERSION {
+    return Err(DecodeError::UnknownVersion(*version));
+  }
+  let (leds, mut body) = body.split_first().ok_or(DecodeError::Corrupt)?;
+  if *leds as usize != SNAPSHOT_LEDS {
+    return Err(DecodeError::Corrupt);
+  }
+  let mut count = 0;
+  while let Some((run, rest)) = body.split_first() {
+    let bytes = rest.get(..SNAPSHOT_LEDS * 4).ok_or(DecodeError::Corrupt)?;
+    let mut frame = [RGBA8::default(); SNAPSHOT_LEDS];
+    for (led, chunk) in frame.iter_mut().zip(bytes.chunks_exact(4)) {
+      *led = RGBA8 { r: chunk[0], g: chunk[1], b: chunk[2], a: chunk[3] };
+    }
+    for _ in 0..*run {
+      *frames.get_mut(count).ok_or(DecodeError::Corrupt)? = frame;
+      count += 1;
+    }
+    body = &rest[SNAPSHOT_LEDS * 4..];
+  }
+  Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+  extern crate std;
+
+  use std::{env, format, fs, path::PathBuf, string::String, vec};
+
+  use super::*;
+  use crate::{effects::EFFECT_COUNT, walker::longest_walker_cycle};
+
+  fn golden_path(id: u8) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("golden").join(format!("effect-{}.bin", id))
+  }
+
+  #[test]
+  fn round_trips_runs_of_frames() {
+    let lit = [SNAPSHOT_COLOR; SNAPSHOT_LEDS];
+    let mut frames = [[RGBA8::default(); SNAPSHOT_LEDS]; 600];
+    frames[400..].fill(lit);
+    let mut buffer = [0; 128];
+    // 255 + 145 dark frames, then 200 lit ones
+    let len = encode(&frames, &mut buffer).unwrap();
+    assert_eq!(len, 2 + 3 * (1 + SNAPSHOT_LEDS * 4));
+    let mut decoded = [[RGBA8::default(); SNAPSHOT_LEDS]; 600];
+    assert_eq!(decode(&buffer[..len], &mut decoded), Ok(600));
+    assert_eq!(decoded, frames);
+    assert_eq!(decode(&[9], &mut decoded), Err(DecodeError::UnknownVersion(9)));
+  }
+
+  #[test]
+  fn covers_a_whole_walker_cycle() {
+    let recorded = Duration::from_millis(SNAPSHOT_FRAMES as u64 * TICK_RATE_IN_MS);
+    assert!(recorded >= longest_walker_cycle(), "{:?} < {:?}", recorded, longest_walker_cycle());
+  }
+
+  #[test]
+  fn golden_frames() {
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+    let mut failures = String::new();
+    for id in 0..EFFECT_COUNT as u8 {
+      let mut frames = vec![[RGBA8::default(); SNAPSHOT_LEDS]; SNAPSHOT_FRAMES];
+      render_snapshot(id.into(), &mut frames);
+      let path = golden_path(id);
+      if update {
+        let mut buffer = vec![0; SNAPSHOT_BUFFER_SIZE];
+        let len = encode(&frames, &mut buffer).unwrap();
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, &buffer[..len]).unwrap();
+        continue;
+      }
+      let Ok(data) = fs::read(&path) else {
+        failures += &format!("\n  {}: missing", path.display());
+        continue;
+      };
+      let mut golden = vec![[RGBA8::default(); SNAPSHOT_LEDS]; SNAPSHOT_FRAMES];
+      match decode(&data, &mut golden) {
+        Ok(SNAPSHOT_FRAMES) => {}
+        other => {
+          failures += &format!("\n  {}: unreadable ({:?})", path.display(), other);
+          continue;
+        }
+      }
+      if let Some(frame) = (0..SNAPSHOT_FRAMES).find(|frame| frames[*frame] != golden[*frame]) {
+        failures += &format!(
+          "\n  effect {} ({:?}) first differs at frame {}: {:?} != {:?}",
+          id, EffectId::from(id).kind(), frame, frames[frame], golden[frame]
+        );
+      }
+    }
+    assert!(
+      failures.is_empty(),
+      "effects no longer match their golden frames:{}\nlook with `cargo run --example snapshots --features snapshot`, \
+       and if the change is intended run `UPDATE_GOLDEN=1 cargo test golden`",
+      failures
+    );
+  }
+}
@@ -0,0 +1,289 @@
+
+use embassy_time::Instant;
+use portable_atomic::{AtomicU8, Ordering};
+
+use crate::{
+  color::{LampColor, COLOR_MAX, RGBA8},
+  effects::{EffectSetting, EFFECT_MAX},
+  persist::Settings,
+  transition::{
+    Transition, Tween, BRIGHTNESS_EASING, BRIGHTNESS_TRANSITION, COLOR_EASING, COLOR_TRANSITION,
+    EFFECT_EASING, EFFECT_TRANSITION
+  }
+};
+
+pub const BRIGHTNESS_INCREMENT: u8 = 16;
+
+#[derive(Default, Debug)]
+pub struct AtomicStore {
+  brightness: AtomicU8,
+  color: AtomicU8,
+  value: AtomicU8,
+  // what toggle_power brings back; not persisted
+  last_brightness: AtomicU8,
+}
+
+impl AtomicStore {
+  pub const fn new() -> Self {
+    Self {
+      brightness: AtomicU8::new(255),
+      color: AtomicU8::new(0),
+      value: AtomicU8::new(0),
+      last_brightness: AtomicU8::new(255),
+    }
+  }
+
+  pub fn settings(&self) -> Settings {
+    Settings {
+      brightness: self.brightness.load(Ordering::Relaxed),
+      color: self.color.load(Ordering::Relaxed),
+      value: self.value.load(Ordering::Relaxed)
+    }
+  }
+
+  pub fn apply_settings(&self, settings: &Settings) {
+    let color = settings.color.min(COLOR_MAX);
+    let value = settings.value.min(EFFECT_MAX);
+    self.brightness.store(settings.brightness, Ordering::Relaxed);
+    self.color.store(color, Ordering::Relaxed);
+    self.value.store(value, Ordering::Relaxed);
+  }
+
+  pub fn reset_state(&self) {
+    self.apply_settings(&Settings::default());
+  }
+
+  pub fn update_brightness(&self, delta: i8) {
+    let brightness = self.brightness.load(Ordering::Relaxed) as i16;
+    let brightness = (brightness + delta as i16 * BRIGHTNESS_INCREMENT as i16).clamp(0, 255);
+    self.brightness.store(brightness as u8, Ordering::Relaxed);
+  }
+
+  // raw brightness units instead of increments, for fine adjustment while the button is held
+  pub fn nudge_brightness(&self, delta: i8) {
+    let brightness = (self.brightness.load(Ordering::Relaxed) as i16 + delta as i16).clamp(0, 255);
+    self.brightness.store(brightness as u8, Ordering::Relaxed);
+  }
+
+  // off remembers the brightness, on restores it (or full, if there's nothing to restore)
+  pub fn toggle_power(&self) {
+    let brightness = self.brightness.load(Ordering::Relaxed);
+    if brightness > 0 {
+      self.last_brightness.store(brightness, Ordering::Relaxed);
+      self.brightness.store(0, Ordering::Relaxed);
+    } else {
+      let last_brightness = match self.last_brightness.load(Ordering::Relaxed) {
+        0 => 255,
+        last_brightness => last_brightness
+      };
+      self.brightness.store(last_brightness, Ordering::Relaxed);
+    }
+  }
+
+  pub fn update_color(&self, delta: i8) {
+    let color = wrapping_step(self.color.load(Ordering::Relaxed), delta, COLOR_MAX);
+    self.color.store(color, Ordering::Relaxed);
+  }
+
+  pub fn update_value(&self, delta: i8) {
+    let value = wrapping_step(self.value.load(Ordering::Relaxed), delta, EFFECT_MAX);
+    self.value.store(value, Ordering::Relaxed);
+  }
+
+  pub fn set_brightness(&self, brightness: u8) {
+    self.brightness.store(brightness, Ordering::Relaxed);
+  }
+
+  pub fn set_color(&self, color: u8) {
+    self.color.store(color.min(COLOR_MAX), Ordering::Relaxed);
+  }
+
+  pub fn set_value(&self, value: u8) {
+    self.value.store(value.min(EFFECT_MAX), Ordering::Relaxed);
+  }
+
+  pub fn get_store(&self) -> Store {
+    let mut color = RGBA8 {
+      r: 0,
+      b: 0,
+      g: 0,
+      a: 0
+    };
+    color.from_u16(self.color.load(Ordering::Relaxed));
+    let value = EffectSetting {
+      effect: self.value.load(Ordering::Relaxed).into(),
+      pct: 255
+    };
+    Store {
+      brightness: self.brightness.load(Ordering::Relaxed),
+      color,
+      value
+    }
+  }
+
+  pub fn update_store(&self, store: &mut Store) {
+    store.update_from(&self.settings());
+  }
+}
+
+// steps through 0..=max, wrapping around at both ends
+fn wrapping_step(current: u8, delta: i8, max: u8) -> u8 {
+  let span = max as i16 + 1;
+  (current.min(max) as i16 + delta as i16).rem_euclid(span) as u8
+}
+
+// the one store shared between the input tasks and the lights
+pub static STORE: AtomicStore = AtomicStore::new();
+
+pub fn reset_state() {
+  STORE.reset_state();
+}
+
+pub fn update_brightness(delta: i8) {
+  STORE.update_brightness(delta);
+}
+
+pub fn nudge_brightness(delta: i8) {
+  STORE.nudge_brightness(delta);
+}
+
+pub fn toggle_power() {
+  STORE.toggle_power();
+}
+
+pub fn update_color(delta: i8) {
+  STORE.update_color(delta);
+}
+
+pub fn update_value(delta: i8) {
+  STORE.update_value(delta);
+}
+
+#[derive(PartialEq)]
+pub struct Store {
+  pub brightness: u8,
+  pub color: RGBA8,
+  pub value: EffectSetting
+}
+
+impl Store {
+  // the target for the renderer to step toward; the effect's progress is left alone
+  pub fn update_from(&mut self, settings: &Settings) {
+    self.brightness = settings.brightness;
+    self.color.from_u16(settings.color);
+    self.value.effect = settings.value.into();
+  }
+}
+
+pub fn get_store() -> Store {
+  STORE.get_store()
+}
+
+pub fn update_store(store: &mut Store) {
+  STORE.update_store(store);
+}
+
+// what step_toward_store is in the middle of, one transition per part of the store
+pub struct StoreTransitions {
+  brightness: Tween<u8>,
+  color: Tween<RGBA8>,
+  effect: Transition,
+}
+
+impl StoreTransitions {
+  pub fn new(local_store: &Store) -> Self {
+    Self {
+      brightness: Tween::new(local_store.brightness, BRIGHTNESS_TRANSITION, BRIGHTNESS_EASING),
+      color: Tween::new(local_store.color, COLOR_TRANSITION, COLOR_EASING),
+      effect: Transition::new(EFFECT_TRANSITION, EFFECT_EASING),
+    }
+  }
+}
+
+// returns true if the mode changed
+pub fn step_toward_store(
+  target_store: &Store,
+  local_store: &mut Store,
+  transitions: &mut StoreTransitions,
+  now: Instant
+) -> bool {
+  transitions.brightness.step(&mut local_store.brightness, &target_store.brightness, now);
+  transitions.color.step(&mut local_store.color, &target_store.color, now);
+  let changed = target_store.value.effect != local_store.value.effect;
+  if changed {
+    local_store.value.effect = target_store.value.effect;
+    transitions.effect.restart(now);
+  }
+  local_store.value.pct = transitions.effect.progress(now);
+  changed
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn settings_round_trip_and_clamp() {
+    let store = AtomicStore::new();
+    store.apply_settings(&Settings { brightness: 12, color: 200, value: 200 });
+    assert_eq!(store.settings(), Settings { brightness: 12, color: COLOR_MAX, value: EFFECT_MAX });
+  }
+
+  #[test]
+  fn color_and_value_wrap_around() {
+    let store = AtomicStore::new();
+    store.update_color(-1);
+    store.update_value(-1);
+    assert_eq!(store.settings(), Settings { brightness: 255, color: COLOR_MAX, value: EFFECT_MAX });
+    store.update_color(1);
+    store.update_value(1);
+    assert_eq!(store.settings(), Settings::default());
+  }
+
+  #[test]
+  fn multi_step_deltas() {
+    let store = AtomicStore::new();
+    store.update_brightness(-3);
+    assert_eq!(store.settings().brightness, 255 - 3 * BRIGHTNESS_INCREMENT);
+    store.update_brightness(100);
+    assert_eq!(store.settings().brightness, 255);
+    store.update_color(-2);
+    assert_eq!(store.settings().color, COLOR_MAX - 1);
+    store.update_color(3);
+    assert_eq!(store.settings().color, 1);
+  }
+
+  #[test]
+  fn power_toggle_restores_brightness() {
+    let store = AtomicStore::new();
+    store.nudge_brightness(-1);
+    store.toggle_power();
+    assert_eq!(store.settings().brightness, 0);
+    store.toggle_power();
+    assert_eq!(store.settings().brightness, 254);
+  }
+
+  #[test]
+  fn step_toward_store_converges_in_time() {
+    let store = AtomicStore::new();
+    let mut local = store.get_store();
+    local.brightness = 0;
+    let mut transitions = StoreTransitions::new(&local);
+    store.update_color(1);
+    store.update_value(1);
+    let target = store.get_store();
+    let start = Instant::from_millis(0);
+    // first step picks up the effect change and restarts the fade
+    assert!(step_toward_store(&target, &mut local, &mut transitions, start));
+    assert_eq!(local.value.pct, 0);
+    assert_eq!(local.brightness, 0);
+    // however often it's stepped in between, it's done once the longest transition is over
+    let mut now = start;
+    while now < start + BRIGHTNESS_TRANSITION.max(COLOR_TRANSITION) {
+      assert!(!step_toward_store(&target, &mut local, &mut transitions, now));
+      now += embassy_time::Duration::from_millis(37);
+    }
+    assert!(!step_toward_store(&target, &mut local, &mut transitions, now));
+    assert!(target == local);
+  }
+}
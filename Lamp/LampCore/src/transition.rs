@@ -0,0 +1,205 @@
+
+//! Transitions measured in time rather than ticks, so TICK_RATE_IN_MS can change
+//! without every fade speeding up or slowing down with it.
+
+use embassy_time::{Duration, Instant};
+
+use crate::color::{ease8_in_out_quad, hsv_lerp, lerp8, RGBA8};
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Easing {
+  Linear,
+  EaseInOut,
+  // fast at first, settling slowly; what the old per tick easing approximated
+  Exponential
+}
+
+impl Easing {
+  // maps linear progress 0..=255 onto the curve, keeping both ends fixed
+  pub fn apply(&self, t: u8) -> u8 {
+    match self {
+      Easing::Linear => t,
+      Easing::EaseInOut => ease8_in_out_quad(t),
+      Easing::Exponential => ease8_out_expo(t),
+    }
+  }
+}
+
+// 1 - 2^(-10t), with 2^-x interpolated linearly between whole powers
+fn ease8_out_expo(t: u8) -> u8 {
+  if t == 255 {
+    return 255;
+  }
+  let exponent = t as u32 * 10;
+  let (whole, fraction) = (exponent / 255, exponent % 255);
+  // 2^-whole scaled to 16 bits, then 2^-fraction ~ 1 - fraction / 2
+  let remaining = ((1u32 << 16) >> whole) * (510 - fraction) / 510;
+  (255 - remaining * 255 / (1 << 16)) as u8
+}
+
+pub const BRIGHTNESS_TRANSITION: Duration = Duration::from_millis(600);
+pub const BRIGHTNESS_EASING: Easing = Easing::Exponential;
+pub const COLOR_TRANSITION: Duration = Duration::from_millis(600);
+pub const COLOR_EASING: Easing = Easing::Exponential;
+// crossfade from the old effect into a new one
+pub const EFFECT_TRANSITION: Duration = Duration::from_millis(320);
+pub const EFFECT_EASING: Easing = Easing::Linear;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Transition {
+  // None once it's done, or if it never started
+  started: Option<Instant>,
+  duration: Duration,
+  easing: Easing,
+}
+
+impl Transition {
+  pub const fn new(duration: Duration, easing: Easing) -> Self {
+    Self { started: None, duration, easing }
+  }
+
+  pub fn restart(&mut self, now: Instant) {
+    self.started = Some(now);
+  }
+
+  pub fn is_running(&self) -> bool {
+    self.started.is_some()
+  }
+
+  // eased 0..=255; 255 from the end of the duration on
+  pub fn progress(&mut self, now: Instant) -> u8 {
+    let progress = self.progress_at(now);
+    if progress == 255 {
+      self.started = None;
+    }
+    progress
+  }
+
+  // progress without finishing the transition, for looking back at an earlier instant
+  fn progress_at(&self, now: Instant) -> u8 {
+    let Some(started) = self.started else {
+      return 255;
+    };
+    let elapsed = now.saturating_duration_since(started).as_millis();
+    let duration = self.duration.as_millis();
+    if elapsed >= duration {
+      return 255;
+    }
+    self.easing.apply((elapsed * 255 / duration) as u8)
+  }
+}
+
+// a value on its way from one setting to the next; retargeting mid way starts
+// again from wherever it is at that moment
+#[derive(Clone, Copy, Debug)]
+pub struct Tween<T> {
+  from: T,
+  to: T,
+  transition: Transition,
+}
+
+pub trait Interpolate: Copy + PartialEq {
+  fn interpolate(from: &Self, to: &Self, pct: u8) -> Self;
+}
+
+impl Interpolate for u8 {
+  fn interpolate(from: &Self, to: &Self, pct: u8) -> Self {
+    lerp8(*from, *to, pct)
+  }
+}
+
+impl Interpolate for RGBA8 {
+  fn interpolate(from: &Self, to: &Self, pct: u8) -> Self {
+    hsv_lerp(from, to, pct)
+  }
+}
+
+impl<T: Interpolate> Tween<T> {
+  pub const fn new(value: T, duration: Duration, easing: Easing) -> Self {
+    Self { from: value, to: value, transition: Transition::new(duration, easing) }
+  }
+
+  // moves `current` toward `target`
+  pub fn step(&mut self, current: &mut T, target: &T, now: Instant) {
+    if *target != self.to {
+      self.from = *current;
+      self.to = *target;
+      self.transition.restart(now);
+    }
+    *current = T::interpolate(&self.from, &self.to, self.transition.progress(now));
+  }
+
+  // like step, for a target that changed at `at` rather than now; a frame that comes
+  // late picks up the curve where it would have been, instead of starting it late
+  pub fn step_since(&mut self, current: &mut T, target: &T, at: Instant, now: Instant) {
+    if *target != self.to {
+      self.from = T::interpolate(&self.from, &self.to, self.transition.progress_at(at));
+      self.to = *target;
+      self.transition.restart(at);
+    }
+    *current = T::interpolate(&self.from, &self.to, self.transition.progress(now));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn curves_keep_their_ends_and_rise() {
+    for easing in [Easing::Linear, Easing::EaseInOut, Easing::Exponential] {
+      assert_eq!(easing.apply(0), 0);
+      assert_eq!(easing.apply(255), 255);
+      assert!((0..255u8).all(|t| easing.apply(t) <= easing.apply(t + 1)));
+    }
+    // exponential is most of the way there early on
+    assert!(Easing::Exponential.apply(64) > 190);
+  }
+
+  #[test]
+  fn progress_depends_on_time_not_calls() {
+    let start = Instant::from_millis(1_000);
+    let mut transition = Transition::new(Duration::from_millis(200), Easing::Linear);
+    assert_eq!(transition.progress(start), 255);
+    transition.restart(start);
+    assert_eq!(transition.progress(start), 0);
+    assert_eq!(transition.progress(start + Duration::from_millis(100)), 127);
+    assert_eq!(transition.progress(start + Duration::from_millis(200)), 255);
+    assert!(!transition.is_running());
+  }
+
+  #[test]
+  fn tween_retargets_from_where_it_is() {
+    let start = Instant::from_millis(0);
+    let mut value = 0u8;
+    let mut tween = Tween::new(value, Duration::from_millis(100), Easing::Linear);
+    tween.step(&mut value, &200, start);
+    tween.step(&mut value, &200, start + Duration::from_millis(50));
+    assert_eq!(value, 100);
+    // turned back half way; goes back down from 100, not from 200
+    tween.step(&mut value, &0, start + Duration::from_millis(50));
+    assert_eq!(value, 100);
+    tween.step(&mut value, &0, start + Duration::from_millis(150));
+    assert_eq!(value, 0);
+  }
+
+  #[test]
+  fn step_since_starts_the_curve_when_the_target_changed() {
+    let start = Instant::from_millis(0);
+    let mut late = 0u8;
+    let mut late_tween = Tween::new(late, Duration::from_millis(100), Easing::Linear);
+    // the target changed at 20 ms, but this frame only comes at 70
+    late_tween.step_since(&mut late, &200, start + Duration::from_millis(20), start + Duration::from_millis(70));
+    assert_eq!(late, 100);
+    // from wherever it was at the new target's instant, not where the last frame left it
+    late_tween.step_since(&mut late, &0, start + Duration::from_millis(45), start + Duration::from_millis(95));
+    let mut on_time = 0u8;
+    let mut on_time_tween = Tween::new(on_time, Duration::from_millis(100), Easing::Linear);
+    on_time_tween.step(&mut on_time, &200, start + Duration::from_millis(20));
+    on_time_tween.step(&mut on_time, &200, start + Duration::from_millis(45));
+    on_time_tween.step(&mut on_time, &0, start + Duration::from_millis(45));
+    on_time_tween.step(&mut on_time, &0, start + Duration::from_millis(95));
+    assert_eq!(late, on_time);
+  }
+}
@@ -1,8 +1,10 @@
 
-use defmt::Format;
 use embassy_time::{Instant, Duration};
 
-use crate::color::{lerp8, LampColor, RGBA8};
+use crate::{
+  color::{lerp8, LampColor, RGBA8},
+  effects::Effect
+};
 
 use rand::{
   distributions::{Standard, Distribution},
@@ -12,10 +14,14 @@ use rand::{
 
 pub const WALKER_INTENSITY_MAX: u8 = 2;
 
-#[derive(PartialEq, Clone, Copy)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub struct WalkerIntensity(u8);
 
 impl WalkerIntensity {
+  pub const fn new(item: u8) -> Self {
+    Self(if item >= WALKER_INTENSITY_MAX { WALKER_INTENSITY_MAX } else { item })
+  }
+
   pub fn to_usize(&self) -> usize {
     self.0 as usize
   }
@@ -23,23 +29,12 @@ impl WalkerIntensity {
 
 impl From<u8> for WalkerIntensity {
   fn from(item: u8) -> Self {
-    if item >= WALKER_INTENSITY_MAX {
-      return Self(WALKER_INTENSITY_MAX);
-    } else {
-      return Self(item);
-    }
+    Self::new(item)
   }
 }
 
-pub const WALKER_FADE_IN_STEP: u8 = 8;
-
-#[derive(PartialEq)]
-pub struct WalkerSetting {
-  pub intensity: WalkerIntensity,
-  pub pct: u8
-}
-
-#[derive(Format, PartialEq, Default, Clone, Copy)]
+#[derive(PartialEq, Default, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum WalkerState {
   #[default]
   Holding,
@@ -53,10 +48,7 @@ pub enum WalkerState {
 
 impl WalkerState {
   fn is_high(&self) -> bool {
-    match self {
-      WalkerState::FadingInHigh | WalkerState::High | WalkerState::FadingOutHigh => true,
-      _ => false
-    }
+    matches!(self, WalkerState::FadingInHigh | WalkerState::High | WalkerState::FadingOutHigh)
   }
 }
 
@@ -147,6 +139,18 @@ static HOLD_CONFIGS: [HoldConfig; (WALKER_INTENSITY_MAX + 1) as usize] = [
   },
 ];
 
+// the most a walker can take to go around hold, fade in, pause and fade out once,
+// at the slowest intensity; how long a recording has to be to see every state
+pub fn longest_walker_cycle() -> Duration {
+  HOLD_CONFIGS
+    .iter()
+    .map(|config| {
+      config.max_hold_time + config.max_transition_in_time + config.max_pause_time + config.max_transition_out_time
+    })
+    .max()
+    .unwrap_or_default()
+}
+
 #[derive(Clone, Copy)]
 pub struct Walker<'a> {
   pub state: WalkerState,
@@ -158,29 +162,38 @@ pub struct Walker<'a> {
 
 impl<'a> Walker<'a> {
 
-  pub fn new_walkers<const N: usize>(intensity: &WalkerIntensity, rng: &mut impl Rng) -> [Walker<'a>; N] {
+  // the frame time and rng are passed in, never read from the system, so a seed and a
+  // sequence of timestamps always give the same frames
+  pub fn new_walkers<const N: usize>(intensity: &WalkerIntensity, now: Instant, rng: &mut impl Rng) -> [Walker<'a>; N] {
     let mut walkers = [Walker::new(); N];
-    Walker::update_walkers(&mut walkers, intensity, rng);
+    Walker::update_walkers(&mut walkers, intensity, now, rng);
     walkers
   }
   
-  pub fn update_walkers<const N: usize>(walkers: &mut [Walker; N], intensity: &WalkerIntensity, rng: &mut impl Rng) {
+  pub fn update_walkers<const N: usize>(walkers: &mut [Walker; N], intensity: &WalkerIntensity, now: Instant, rng: &mut impl Rng) {
     // info!("Updating walkers with intensity: {:?}", intensity.to_usize());
     for (idx, walker) in walkers.iter_mut().enumerate() {
-      walker.update_walker(intensity, rng, idx);
+      walker.update_walker(intensity, now, rng, idx);
     }
   }
   
-  pub fn run_walkers<const N: usize>(data: &mut [RGBA8; N], walkers: &mut [Walker; N], color: &RGBA8, rng: &mut impl Rng) {
+  pub fn run_walkers<const N: usize>(
+    data: &mut [RGBA8; N],
+    walkers: &mut [Walker; N],
+    color: &RGBA8,
+    now: Instant,
+    rng: &mut impl Rng
+  ) {
     for (walker, led) in walkers.iter_mut().zip(data.iter_mut()) { 
-      walker.run_walker(led, color, rng);
+      walker.run_walker(led, color, now, rng);
     }
   }
 
   fn new() -> Self {
     Self {
       state: WalkerState::default(),
-      last_time: Instant::now(),
+      // overwritten by the first update_walkers
+      last_time: Instant::from_ticks(0),
       time_in_state: Duration::default(),
       hold_config: &HOLD_CONFIGS[0],
       pause_value: 0,
@@ -198,7 +211,7 @@ impl<'a> Walker<'a> {
     self.pause_value = rng.gen_range(range);
   }
 
-  fn update_walker(&mut self, intensity: &WalkerIntensity, rng: &mut impl Rng, idx: usize) {
+  fn update_walker(&mut self, intensity: &WalkerIntensity, now: Instant, rng: &mut impl Rng, idx: usize) {
     self.hold_config = &HOLD_CONFIGS[intensity.to_usize()];
     self.state = if idx == 0 {
       // holding means it will transition to  low
@@ -209,15 +222,15 @@ impl<'a> Walker<'a> {
     };
     // may not be needed but meh
     self.set_pause_value(rng);
-    self.transition_state(rng);
+    self.transition_state(now, rng);
     // info!("Started Walker: {:?}; in state: {:?}", idx + 1, self.state);
   }
 
-  fn run_walker(&mut self, led: &mut RGBA8, color: &RGBA8, rng: &mut impl Rng) {
-    if self.last_time.elapsed() > self.time_in_state {
-      self.transition_state(rng);
+  fn run_walker(&mut self, led: &mut RGBA8, color: &RGBA8, now: Instant, rng: &mut impl Rng) {
+    if now.saturating_duration_since(self.last_time) > self.time_in_state {
+      self.transition_state(now, rng);
     }
-    let fade_pct = self.get_current_pct();
+    let fade_pct = self.get_current_pct(now);
     let pct = match self.state {
         WalkerState::Holding => self.hold_config.hold_value,
         WalkerState::FadingInLow | WalkerState::FadingInHigh => {
@@ -231,16 +244,11 @@ impl<'a> Walker<'a> {
     led.fade_from(color, pct);
   }
 
-  fn get_current_pct(&self) -> u8 {
-    let raw_pct =  (
-      self.last_time.elapsed().as_millis() as f32
-    ) / (
-      self.time_in_state.as_millis() as f32
-    );
-    (255.0f32 * raw_pct).min(255.0).max(0.0) as u8
+  fn get_current_pct(&self, now: Instant) -> u8 {
+    elapsed_pct(now.saturating_duration_since(self.last_time), self.time_in_state)
   }
 
-  fn transition_state(&mut self, rng: &mut impl Rng) {
+  fn transition_state(&mut self, now: Instant, rng: &mut impl Rng) {
     let config = self.hold_config;
     let min_millis;
     let max_millis;
@@ -285,6 +293,67 @@ impl<'a> Walker<'a> {
       }
     };
     self.time_in_state = Duration::from_millis(rng.gen_range(min_millis..=max_millis));
-    self.last_time = Instant::now();
+    self.last_time = now;
+  }
+}
+
+// how far through `total` we are, 0..=255; integer only, since this runs for every pixel every tick
+fn elapsed_pct(elapsed: Duration, total: Duration) -> u8 {
+  let total = total.as_millis();
+  if total == 0 {
+    return 255;
+  }
+  (elapsed.as_millis().saturating_mul(255) / total).min(255) as u8
+}
+
+// the walkers as one of the registered effects
+pub struct WalkerEffect<const N: usize> {
+  intensity: WalkerIntensity,
+  walkers: [Walker<'static>; N]
+}
+
+impl<const N: usize> WalkerEffect<N> {
+  pub fn new(intensity: WalkerIntensity) -> Self {
+    Self {
+      intensity,
+      walkers: [Walker::new(); N]
+    }
+  }
+}
+
+impl<const N: usize> Effect<N> for WalkerEffect<N> {
+  fn start(&mut self, now: Instant, rng: &mut impl Rng) {
+    Walker::update_walkers(&mut self.walkers, &self.intensity, now, rng);
   }
-}
\ No newline at end of file
+
+  fn render(&mut self, now: Instant, color: &RGBA8, data: &mut [RGBA8; N], rng: &mut impl Rng) {
+    Walker::run_walkers(data, &mut self.walkers, color, now, rng);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // what get_current_pct used to compute; min then max, so 0 / 0 still comes out as 255
+  #[allow(clippy::manual_clamp)]
+  fn elapsed_pct_f32(elapsed: Duration, total: Duration) -> u8 {
+    let raw_pct = elapsed.as_millis() as f32 / total.as_millis() as f32;
+    (255.0f32 * raw_pct).min(255.0).max(0.0) as u8
+  }
+
+  #[test]
+  fn elapsed_pct_matches_the_f32_curve() {
+    let totals = HOLD_CONFIGS.iter().flat_map(|config| [
+      config.min_hold_time, config.max_transition_in_time, config.min_pause_time, config.max_transition_out_time
+    ]);
+    for total in totals.chain([Duration::from_millis(0), Duration::from_millis(1)]) {
+      for elapsed in 0..=total.as_millis() + 50 {
+        let elapsed = Duration::from_millis(elapsed);
+        // float rounding can land one below an exact integer division, never further off
+        let (fixed, float) = (elapsed_pct(elapsed, total), elapsed_pct_f32(elapsed, total));
+        assert!(fixed == float || fixed == float + 1, "{} vs {} at {:?}/{:?}", fixed, float, elapsed, total);
+      }
+    }
+  }
+}
@@ -0,0 +1,261 @@
+
+//! Runs the lamp firmware logic in a terminal, so HOLD_CONFIGS and friends can
+//! be tuned without flashing a board.
+//!
+//! usage: lamp-sim [led count] [seed]   (5 or 12, defaults to 5; random seed by default)
+//!
+//! keys:
+//!   right / up / +     encoder turn (increment); hold the key to spin fast
+//!   left / down / -    encoder turn (decrement)
+//!   [ / ]              turn while holding the button (fine adjustment)
+//!   space / enter      click
+//!   d                  double click (power toggle)
+//!   t                  triple click (recall the next preset; saves to a free slot while in color / value)
+//!   l                  long press (reset, undoable; starts the factory reset countdown)
+//!   v                  very long press (factory reset)
+//!   u                  undo the last reset
+//!   q / esc            quit
+
+use std::{
+  io::{self, Write},
+  time::{Duration, Instant}
+};
+
+use crossterm::{
+  cursor,
+  event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+  style::{Color, Print, ResetColor, SetForegroundColor},
+  terminal, queue, execute
+};
+use rand::random;
+
+use lamp_core::{
+  color::RGBA8,
+  effects::EffectId,
+  encoder::Acceleration,
+  gesture::Gesture,
+  history::ResetHistory,
+  manager::{reduce, Command, ManagerState, MODE_TIMEOUT_IN_SECONDS, SAVE_TIMEOUT_IN_MILISECONDS},
+  overlay::{MODE_INDICATION, RESET_COUNTDOWN},
+  output::{LedOutput, Recorder},
+  power::{estimated_current_ma, DESK_BUDGET},
+  presets::{PresetSlots, SavedSlots},
+  render::{Renderer, TICK_RATE_IN_MS},
+  rng::LampRng,
+  store::{nudge_brightness, reset_state, toggle_power, update_brightness, update_color, update_value, STORE},
+  Events
+};
+
+// runs the same reducer as manager_task, with the timeout / save tasks replaced by deadlines
+struct Manager {
+  state: ManagerState,
+  mode_deadline: Option<Instant>,
+  save_deadline: Option<Instant>,
+  saves: u32,
+  // kept in memory only; the sim has no flash
+  presets: PresetSlots,
+  history: ResetHistory,
+}
+
+impl Manager {
+  fn new() -> Self {
+    Self {
+      // the sim has no button to let go of, so a long press resets and the countdown just runs out
+      state: ManagerState::new(SavedSlots::new(), false),
+      mode_deadline: None,
+      save_deadline: None,
+      saves: 0,
+      presets: PresetSlots::new(),
+      history: ResetHistory::new(),
+    }
+  }
+
+  fn handle(&mut self, event: Events, now: Instant) {
+    let (state, commands) = reduce(self.state, event, embassy_time::Instant::now());
+    self.state = state;
+    for command in commands {
+      self.run(command, now);
+    }
+  }
+
+  fn run(&mut self, command: Command, now: Instant) {
+    match command {
+      Command::StartModeTimeout => self.mode_deadline = Some(now + Duration::from_secs(MODE_TIMEOUT_IN_SECONDS)),
+      Command::ResetModeTimeout => {
+        if self.mode_deadline.is_some() {
+          self.run(Command::StartModeTimeout, now);
+        }
+      }
+      Command::StopModeTimeout => self.mode_deadline = None,
+      Command::ScheduleSave => self.save_deadline = Some(now + Duration::from_millis(SAVE_TIMEOUT_IN_MILISECONDS)),
+      Command::WriteStore => self.saves += 1,
+      Command::ShowMode(mode) => MODE_INDICATION.show(mode),
+      Command::StartCountdown => RESET_COUNTDOWN.start(),
+      Command::CancelCountdown => RESET_COUNTDOWN.cancel(),
+      Command::UpdateBrightness(delta) => update_brightness(delta),
+      Command::NudgeBrightness(delta) => nudge_brightness(delta),
+      Command::UpdateColor(delta) => update_color(delta),
+      Command::UpdateValue(delta) => update_value(delta),
+      Command::TogglePower => toggle_power(),
+      Command::ResetStore => {
+        self.history.push(STORE.settings());
+        reset_state();
+      }
+      Command::UndoReset => {
+        if let Some(settings) = self.history.pop() {
+          STORE.apply_settings(&settings);
+          self.run(Command::ScheduleSave, now);
+        }
+      }
+      Command::ClearPresets => self.presets.clear(),
+      // memory never fails to take it, so the save is reported back right away
+      Command::SavePreset(slot, name) => {
+        if self.presets.save_current(slot, &name, &STORE).is_some() {
+          self.handle(Events::PresetSaved(slot), now);
+        }
+      }
+      Command::RecallPreset(slot) => {
+        self.presets.recall(slot, &STORE);
+      }
+    }
+  }
+
+  fn poll_deadlines(&mut self, now: Instant) {
+    if self.mode_deadline.is_some_and(|deadline| now >= deadline) {
+      self.mode_deadline = None;
+      self.handle(Events::ModeTimeout, now);
+    }
+    if self.save_deadline.is_some_and(|deadline| now >= deadline) {
+      self.save_deadline = None;
+      self.handle(Events::SaveStore, now);
+    }
+  }
+}
+
+// key repeat stands in for spinning the knob
+fn key_to_event(key: KeyEvent, acceleration: &mut Acceleration) -> Option<Events> {
+  let now = embassy_time::Instant::now();
+  match key.code {
+    KeyCode::Right | KeyCode::Up | KeyCode::Char('+') | KeyCode::Char('=') => {
+      Some(Events::EncoderTurn(acceleration.step(now, true)))
+    }
+    KeyCode::Left | KeyCode::Down | KeyCode::Char('-') => Some(Events::EncoderTurn(acceleration.step(now, false))),
+    KeyCode::Char(']') => Some(Events::Button(Gesture::HoldTurn(1))),
+    KeyCode::Char('[') => Some(Events::Button(Gesture::HoldTurn(-1))),
+    KeyCode::Char(' ') | KeyCode::Enter => Some(Events::Button(Gesture::Click)),
+    KeyCode::Char('d') => Some(Events::Button(Gesture::DoubleClick)),
+    KeyCode::Char('t') => Some(Events::Button(Gesture::TripleClick)),
+    KeyCode::Char('l') => Some(Events::Button(Gesture::LongPress)),
+    KeyCode::Char('v') => Some(Events::Button(Gesture::VeryLongPress)),
+    KeyCode::Char('u') => Some(Events::Undo),
+    _ => None
+  }
+}
+
+fn is_quit(key: &KeyEvent) -> bool {
+  match key.code {
+    KeyCode::Char('q') | KeyCode::Esc => true,
+    KeyCode::Char('c') => key.modifiers.contains(KeyModifiers::CONTROL),
+    _ => false
+  }
+}
+
+// terminals have no white channel; fold it back in as a warm white
+fn to_terminal_color(led: &RGBA8) -> Color {
+  let white = led.a as u16;
+  Color::Rgb {
+    r: (led.r as u16 + white).min(255) as u8,
+    g: (led.g as u16 + white * 200 / 255).min(255) as u8,
+    b: (led.b as u16 + white * 140 / 255).min(255) as u8,
+  }
+}
+
+fn draw<const N: usize>(out: &mut impl Write, output: &Recorder<N, 1>, manager: &Manager) -> io::Result<()> {
+  let dark = [RGBA8::default(); N];
+  let frame = output.last().filter(|_| output.is_enabled()).unwrap_or(&dark);
+  let settings = STORE.settings();
+  queue!(out, cursor::MoveToColumn(0), terminal::Clear(terminal::ClearType::CurrentLine))?;
+  for led in frame.iter() {
+    queue!(out, SetForegroundColor(to_terminal_color(led)), Print("████ "))?;
+  }
+  queue!(
+    out,
+    ResetColor,
+    Print(format!(
+      " {:?} | brightness {:3} color {:2} effect {:?} | {:4}mA | preset {} | saves {}",
+      manager.state.mode,
+      settings.brightness,
+      settings.color,
+      EffectId::from(settings.value).kind(),
+      estimated_current_ma(),
+      manager.state.last_preset.map_or('-', |slot| (b'0' + slot) as char),
+      manager.saves
+    ))
+  )?;
+  out.flush()
+}
+
+fn run<const N: usize>(out: &mut impl Write, seed: u64) -> io::Result<()> {
+  let mut rng = LampRng::new(seed);
+  let mut renderer = Renderer::<N>::new(embassy_time::Instant::now(), &mut rng, DESK_BUDGET);
+  let mut manager = Manager::new();
+  let mut acceleration = Acceleration::new();
+  let tick = Duration::from_millis(TICK_RATE_IN_MS);
+  let mut next_tick = Instant::now();
+  // the same output trait the lamp writes its strip through, into memory instead
+  let mut output = Recorder::<N, 1>::new();
+  output.set_enabled(true);
+  output.record(renderer.off_frame());
+  draw(out, &output, &manager)?;
+  loop {
+    next_tick += tick;
+    // drain input until the next frame is due
+    while let Some(remaining) = next_tick.checked_duration_since(Instant::now()) {
+      if !event::poll(remaining)? {
+        break;
+      }
+      if let Event::Key(key) = event::read()? {
+        if key.kind != KeyEventKind::Press {
+          continue;
+        }
+        if is_quit(&key) {
+          return Ok(());
+        }
+        if let Some(lamp_event) = key_to_event(key, &mut acceleration) {
+          manager.handle(lamp_event, Instant::now());
+        }
+      }
+    }
+    manager.poll_deadlines(Instant::now());
+    output.record(renderer.next_frame(embassy_time::Instant::now(), &mut rng));
+    draw(out, &output, &manager)?;
+  }
+}
+
+fn main() -> io::Result<()> {
+  // the pixel count is a const generic, so only the board variants we build are supported
+  let run_board = match std::env::args().nth(1).as_deref() {
+    None | Some("5") => run::<5>,
+    Some("12") => run::<12>,
+    Some(other) => {
+      eprintln!("unsupported led count {}; expected 5 or 12", other);
+      std::process::exit(1);
+    }
+  };
+  let seed = match std::env::args().nth(2).map(|seed| seed.parse::<u64>()) {
+    None => random(),
+    Some(Ok(seed)) => seed,
+    Some(Err(_)) => {
+      eprintln!("seed must be a number");
+      std::process::exit(1);
+    }
+  };
+  let mut out = io::stdout();
+  terminal::enable_raw_mode()?;
+  execute!(out, cursor::Hide)?;
+  let result = run_board(&mut out, seed);
+  // printed on the way out, so an interesting run can be started again
+  execute!(out, ResetColor, cursor::Show, Print(format!("\r\nseed {}\r\n", seed)))?;
+  terminal::disable_raw_mode()?;
+  result
+}